@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Limits on how long a search may run. The search stops as soon as any of the limits is reached,
+/// and the best action found up to that point is used.
+///
+/// A budget without any limits never runs out on its own, so it should at least be given a
+/// cancellation flag.
+#[derive(Clone, Debug, Default)]
+pub struct SearchBudget {
+    /// the most search iterations to run
+    pub iterations: Option<usize>,
+    /// the most wall-clock time to spend
+    pub time: Option<Duration>,
+    /// the most nodes a tree may grow to, ignored by searches which don't build a tree
+    pub max_nodes: Option<usize>,
+    /// stops the search once set to true, e.g. from another thread
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl SearchBudget {
+    pub fn iterations(iterations: usize) -> Self {
+        SearchBudget {
+            iterations: Some(iterations),
+            ..Default::default()
+        }
+    }
+
+    pub fn time(time: Duration) -> Self {
+        SearchBudget {
+            time: Some(time),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = Some(iterations);
+        self
+    }
+
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Starts the clock on the budget.
+    pub fn start(&self) -> BudgetTracker<'_> {
+        BudgetTracker {
+            budget: self,
+            deadline: self.time.map(|time| Instant::now() + time),
+        }
+    }
+
+    /// Runs `search` on `tree` until the budget runs out, returning the number of iterations run.
+    /// `num_nodes` is checked against `max_nodes` before every iteration.
+    pub(crate) fn run<T, E>(&self, tree: &mut T, num_nodes: fn(&T) -> usize, mut search: impl FnMut(&mut T) -> Result<(), E>) -> Result<usize, E> {
+        let tracker = self.start();
+        let mut iterations = 0;
+
+        while !tracker.is_exhausted(iterations, num_nodes(tree)) {
+            search(tree)?;
+            iterations += 1;
+        }
+
+        Ok(iterations)
+    }
+}
+
+/// A budget which has been started, used by a search to check whether it should keep going.
+pub struct BudgetTracker<'a> {
+    budget: &'a SearchBudget,
+    deadline: Option<Instant>,
+}
+
+impl BudgetTracker<'_> {
+    /// Whether the search has used up its budget after running `iterations` iterations and
+    /// growing to `nodes` nodes.
+    pub fn is_exhausted(&self, iterations: usize, nodes: usize) -> bool {
+        if self.budget.iterations.is_some_and(|max| iterations >= max) {
+            return true;
+        }
+
+        if self.budget.max_nodes.is_some_and(|max| nodes >= max) {
+            return true;
+        }
+
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return true;
+        }
+
+        if let Some(cancel) = &self.budget.cancel {
+            return cancel.load(Ordering::Relaxed);
+        }
+
+        false
+    }
+}
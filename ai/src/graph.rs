@@ -4,9 +4,12 @@ use petgraph::{Directed};
 use petgraph::prelude::StableGraph;
 use petgraph::stable_graph::NodeIndex;
 use rand::{Rng, RngCore, SeedableRng};
-use crate::{Determinable, ismcts_mt, mcts};
+use crate::{Determinable, MctsConfig, RootParallelTrees, RngStreams, SearchError};
 use crate::ismcts::{ISMCTSParams};
 use crate::mcts::{Mcts};
+use crate::parallel::num_workers;
+
+pub type GameGraph<G, A> = StableGraph<GraphNode<G>, GraphEdge<A>, Directed>;
 
 #[derive(Clone, Eq, PartialEq)]
 pub struct GraphNode<S: Clone + Eq + PartialEq> {
@@ -71,20 +74,21 @@ pub trait Initializer<P, A: Send, S: Mcts<P, A>> {
 }
 
 #[allow(dead_code)]
-pub fn generate_graph<P,A,R,G,I>(sim_params: ISMCTSParams) -> StableGraph<GraphNode<G>, GraphEdge<A>, Directed>
+pub fn generate_graph<P,A,R,G,I>(sim_params: ISMCTSParams) -> Result<GameGraph<G, A>, SearchError<G::Error>>
     where
         P: Eq + PartialEq + Hash + Send + Sync + Clone,
         A: Clone + Eq + PartialEq + Hash + Send + Sync + Debug,
         R: RngCore + SeedableRng + Clone + Send + Sync,
         G: Clone + Eq + PartialEq + Mcts<P, A> + Send + Determinable<P, A, G>,
-        I: Initializer<P, A, G>
+        I: Initializer<P, A, G>,
+        G::Error: Send
 {
-    let mut graph: StableGraph<GraphNode<G>, GraphEdge<A>, Directed> = StableGraph::new();
+    let mut graph: GameGraph<G, A> = StableGraph::new();
     let mut nodes: Vec<(NodeIndex, G)> = Vec::new();
 
     for sim_n in 0..sim_params.num_sims {
         let mut not_rng = R::seed_from_u64(sim_params.seed);
-        let mut per_sim_rng = R::seed_from_u64(sim_params.seed + (sim_n as u64));
+        let mut per_sim_rng: R = RngStreams::from_seed(sim_params.seed).stream(sim_n as u64);
 
         let mut game = I::initialize(&mut not_rng);
         let players = game.players();
@@ -96,14 +100,34 @@ pub fn generate_graph<P,A,R,G,I>(sim_params: ISMCTSParams) -> StableGraph<GraphN
 
         step += 1;
 
+        // each player keeps their own trees for the whole game, so earlier searches aren't wasted,
+        // one tree per core
+        let mut trees: Vec<Option<RootParallelTrees<P, A, G>>> = players.iter().map(|_| None).collect();
+
         loop {
             let current_player_idx = players.iter().enumerate().find(|(_, p)| **p == game.current_player()).unwrap().0;
             let sim_player = &sim_params.sim_players[current_player_idx];
-            let ai_selected_action = mcts(&game, &mut per_sim_rng, sim_player.num_simulations_per_action);
+
+            let tree = trees[current_player_idx].get_or_insert_with(|| {
+                RootParallelTrees::from_state(game.clone(), MctsConfig::default(), num_workers(sim_params.max_cores), sim_params.max_cores)
+            });
+            match &sim_player.budget {
+                Some(budget) => tree.search_budget(&mut per_sim_rng, budget).map(|_| ())?,
+                None => {
+                    // the iterations are shared out between the trees, rather than run by each
+                    let iterations_per_tree = sim_player.num_simulations_per_action.div_ceil(tree.trees().len());
+                    tree.search_n(&mut per_sim_rng, iterations_per_tree)?
+                }
+            }
+            let ai_selected_action = tree.best_action().ok_or(SearchError::NoActions)?;
 
             let prev_node_idx = nodes.last().expect("should be at least one node in place before this point").0;
 
-            game = game.apply_action(ai_selected_action.clone(), &mut per_sim_rng).unwrap();
+            game = game.apply_action(ai_selected_action.clone(), &mut per_sim_rng).map_err(SearchError::IllegalAction)?;
+
+            for tree in trees.iter_mut().flatten() {
+                tree.advance(&ai_selected_action, game.clone());
+            }
 
             let new_node_idx = add_state_to_graph(&mut graph, &mut nodes, &game, sim_n, step);
             add_action_to_graph(&mut graph, ai_selected_action, prev_node_idx, new_node_idx);
@@ -116,5 +140,5 @@ pub fn generate_graph<P,A,R,G,I>(sim_params: ISMCTSParams) -> StableGraph<GraphN
         }
     }
 
-    graph
+    Ok(graph)
 }
\ No newline at end of file
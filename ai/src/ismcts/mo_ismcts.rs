@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use crate::budget::SearchBudget;
+use crate::mcts::{Mcts, MctsConfig, SearchError};
+use crate::mcts::mcts::best_or_first_action;
+use crate::ismcts::{Determinable, Observable};
+use crate::ismcts::so_ismcts::InfoSetNodes;
+
+/// Multiple-observer information set MCTS.
+///
+/// Keeps one tree per player. All trees are descended in lockstep, but each player's tree branches
+/// on that player's observation of the action, so moves which look identical to a player (e.g. an
+/// opponent revealing an unknown card) share a node in that player's tree.
+///
+/// Uses the same parts of the config as `InfoSetTree`.
+pub struct MultiObserverTrees<P, A, G: Mcts<P, A>> {
+    config: MctsConfig<P, A, G>,
+    observer: P,
+    root_state: G,
+    trees: HashMap<P, InfoSetNodes<P, A>>,
+}
+
+impl<
+    P: Eq + PartialEq + Hash + Clone,
+    A: Eq + PartialEq + Clone,
+    G: Mcts<P, A> + Determinable<P, A, G> + Observable<P, A>
+> MultiObserverTrees<P, A, G> {
+    pub fn from_state(state: G, config: MctsConfig<P, A, G>) -> Self {
+        let trees = state.players().into_iter().map(|player| (player, InfoSetNodes::new())).collect();
+
+        MultiObserverTrees {
+            config,
+            observer: state.current_player(),
+            root_state: state,
+            trees,
+        }
+    }
+
+    pub fn search_n<R: Rng>(&mut self, rng: &mut R, iterations: usize) -> Result<(), SearchError<G::Error>> {
+        for _ in 0..iterations {
+            self.search(rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Searches until the budget runs out, returning the number of iterations run.
+    pub fn search_budget<R: Rng>(&mut self, rng: &mut R, budget: &SearchBudget) -> Result<usize, SearchError<G::Error>> {
+        budget.run(self, Self::num_nodes, |tree| tree.search(rng))
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.trees.values().map(|tree| tree.len()).sum()
+    }
+
+    /// The number of nodes in `player`'s tree, `None` if they aren't one of the players.
+    pub fn num_nodes_of(&self, player: &P) -> Option<usize> {
+        self.trees.get(player).map(|tree| tree.len())
+    }
+
+    /// The most visited action at the root of the observer's tree.
+    pub fn best_action(&self) -> Option<A> {
+        self.trees[&self.observer].most_visited_action(&self.root_state.actions())
+    }
+
+    pub fn search<R: Rng>(&mut self, rng: &mut R) -> Result<(), SearchError<G::Error>> {
+        // sample a state consistent with what the observer knows
+        let mut state = self.root_state.determine(rng, self.observer.clone());
+
+        let mut current_node_idxs: HashMap<P, usize> = self.trees.keys().map(|player| (player.clone(), 0)).collect();
+
+        // track visited nodes of every tree for back propagation
+        let mut visited_nodes: HashMap<P, Vec<usize>> = self.trees.keys().map(|player| (player.clone(), vec![0])).collect();
+
+        loop {
+            if state.outcome().is_some() {
+                break;
+            }
+
+            let actions = state.actions();
+            if actions.is_empty() {
+                return Err(SearchError::NoActions);
+            }
+
+            let mover = state.current_player();
+            let mover_node_idx = current_node_idxs[&mover];
+
+            // the mover chooses based on their own view of the actions
+            let observed_actions: Vec<A> = actions.iter().map(|action| state.observe(action, &mover)).collect();
+
+            let untried: Vec<usize> = (0..actions.len())
+                .filter(|idx| self.trees[&mover].child_with_action(mover_node_idx, &observed_actions[*idx]).is_none())
+                .collect();
+
+            let (action, expanded) = if let Some(action_idx) = untried.choose(rng) {
+                (actions[*action_idx].clone(), true)
+            } else {
+                let tree = self.trees.get_mut(&mover).expect("a tree per player");
+                let selected_idx = tree.select(mover_node_idx, &observed_actions, self.config.exploration_constant, self.config.noise, rng).ok_or(SearchError::NoActions)?;
+                let selected_observation = tree.action(selected_idx);
+
+                let action_idx = observed_actions.iter()
+                    .position(|observed| observed == selected_observation)
+                    .expect("selected a legal action");
+
+                (actions[action_idx].clone(), false)
+            };
+
+            // every player's tree follows the action as they observe it
+            for (player, tree) in self.trees.iter_mut() {
+                let observed = state.observe(&action, player);
+                let node_idx = current_node_idxs[player];
+
+                let child_idx = match tree.child_with_action(node_idx, &observed) {
+                    Some(child_idx) => child_idx,
+                    None => tree.add_node(observed, mover.clone(), node_idx),
+                };
+
+                current_node_idxs.insert(player.clone(), child_idx);
+                visited_nodes.get_mut(player).expect("a path per player").push(child_idx);
+            }
+
+            state = state.apply_action(action, rng).map_err(SearchError::IllegalAction)?;
+
+            if expanded {
+                break;
+            }
+        }
+
+        let rewards = self.config.leaf_rewards(&state, &self.root_state.players(), rng, None)?;
+
+        for (player, tree) in self.trees.iter_mut() {
+            tree.backpropagate(&visited_nodes[player], &rewards);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn mo_ismcts<
+    R: Rng + Sized,
+    P: Eq + PartialEq + Hash + Clone,
+    A: Eq + PartialEq + Clone,
+    G: Mcts<P, A> + Determinable<P, A, G> + Observable<P, A>
+>(game: &G, rng: &mut R, num_iterations: usize, config: MctsConfig<P, A, G>) -> Result<A, SearchError<G::Error>> {
+    let mut trees = MultiObserverTrees::from_state(game.clone(), config);
+
+    trees.search_n(rng, num_iterations)?;
+
+    best_or_first_action(game, trees.best_action())
+}
@@ -1,98 +1,224 @@
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use rand::{Rng, RngCore};
-use crate::mcts::{Outcome};
-use crate::mcts::Mcts;
-use crate::mcts::random_rollout;
+use rand::{Rng, RngCore, SeedableRng};
+use crate::budget::SearchBudget;
+use crate::parallel::run_bounded;
+use crate::rng_streams::RngStreams;
+use crate::mcts::{EscapePolicy, Mcts, RolloutPolicy, SearchError, UniformRollout};
+
+pub mod so_ismcts;
+pub mod mo_ismcts;
+
+pub use so_ismcts::{so_ismcts, InfoSetTree};
+pub use mo_ismcts::{mo_ismcts, MultiObserverTrees};
 
 pub trait Determinable<P, A, G: Mcts<P, A>> {
     fn determine<R: Rng>(&self, rng: &mut R, perspective_player: P) -> G;
 }
 
-type Determinizations<A, P> = Vec<HashMap<A, HashMap<P, f64>>>;
+/// Describes what each player gets to see of an action.
+///
+/// `observe` is called on the state the action is about to be applied to, and should return the
+/// action as the observer perceives it. Actions which the observer can't tell apart must map to
+/// the same value, for example an opponent revealing one of their hidden cards.
+pub trait Observable<P, A> {
+    fn observe(&self, action: &A, observer: &P) -> A;
+}
 
+/// Per determinization, the number of rollouts and the average rewards of each action.
+type Determinizations<A, P> = Vec<HashMap<A, (usize, HashMap<P, f64>)>>;
+
+/// Aggregated statistics of an action across all determinizations, from the
+/// perspective of the player who is to move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionStats {
+    /// mean reward of the action over all determinizations
+    pub mean: f64,
+    /// population variance of the per-determinization mean rewards
+    pub variance: f64,
+    /// number of determinizations the action was evaluated in
+    pub num_determinizations: usize,
+    /// number of rollouts performed for the action, summed over all determinizations
+    pub num_simulations: usize,
+}
 
+/// Picks the action with the best mean reward over `num_determinizations` determinizations, each
+/// giving every action `num_simulations` rollouts, on at most `max_cores` threads, where 0 uses
+/// every available core.
 #[allow(dead_code)]
 pub fn ismcts_mt<
-    R: Rng + RngCore + Sized + Clone + Send,
-    P: Eq + PartialEq + Hash + Send + Sync,
+    R: Rng + RngCore + SeedableRng + Clone + Send,
+    P: Eq + PartialEq + Hash + Send + Sync + Clone,
+    A: Eq + PartialEq + Hash + Send + Sync + Clone,
+    G: Mcts<P, A> + Determinable<P, A, G> + Send
+>(game: &G, rng: &R, num_determinizations: usize, num_simulations: usize, escape_policy: EscapePolicy, max_cores: usize) -> Result<A, SearchError<G::Error>>
+    where G::Error: Send
+{
+    ismcts_mt_with_budget(game, rng, num_determinizations, &SearchBudget::iterations(num_simulations), escape_policy, &UniformRollout, max_cores)
+}
+
+/// Like `ismcts_mt`, but every determinization keeps simulating with the given rollout policy until
+/// the budget runs out. The budget's iterations count the rounds of one rollout per action within
+/// each determinization.
+///
+/// Determinizations are simulated on at most `max_cores` threads, where 0 uses every available core.
+/// A time limit is shared by all of them, so with fewer cores than determinizations the last ones
+/// to be picked up may get no simulations, and are left out of the statistics.
+pub fn ismcts_mt_with_budget<
+    R: Rng + RngCore + SeedableRng + Clone + Send,
+    P: Eq + PartialEq + Hash + Send + Sync + Clone,
+    A: Eq + PartialEq + Hash + Send + Sync + Clone,
+    G: Mcts<P, A> + Determinable<P, A, G> + Send
+>(game: &G, rng: &R, num_determinizations: usize, budget: &SearchBudget, escape_policy: EscapePolicy, rollout: &dyn RolloutPolicy<P, A, G>, max_cores: usize) -> Result<A, SearchError<G::Error>>
+    where G::Error: Send
+{
+    let stats = ismcts_mt_stats_with_budget(game, rng, num_determinizations, budget, escape_policy, rollout, max_cores)?;
+
+    // walk the actions in their original order so ties resolve deterministically
+    let best = game.actions().into_iter().fold(None, |acc: Option<(A, f64)>, action| {
+        let mean = stats.get(&action).map(|s| s.mean).unwrap_or(0f64);
+
+        match acc {
+            Some(acc) if acc.1 >= mean => Some(acc),
+            _ => Some((action, mean)),
+        }
+    });
+
+    best.map(|(action, _)| action).ok_or(SearchError::NoActions)
+}
+
+pub fn ismcts_mt_stats<
+    R: Rng + RngCore + SeedableRng + Clone + Send,
+    P: Eq + PartialEq + Hash + Send + Sync + Clone,
     A: Eq + PartialEq + Hash + Send + Sync + Clone,
     G: Mcts<P, A> + Determinable<P, A, G> + Send
->(game: &G, rng: &R, num_determinizations: usize, num_simulations: usize) -> A {
+>(game: &G, rng: &R, num_determinizations: usize, num_simulations: usize, escape_policy: EscapePolicy, max_cores: usize) -> Result<HashMap<A, ActionStats>, SearchError<G::Error>>
+    where G::Error: Send
+{
+    ismcts_mt_stats_with_budget(game, rng, num_determinizations, &SearchBudget::iterations(num_simulations), escape_policy, &UniformRollout, max_cores)
+}
 
+pub fn ismcts_mt_stats_with_budget<
+    R: Rng + RngCore + SeedableRng + Clone + Send,
+    P: Eq + PartialEq + Hash + Send + Sync + Clone,
+    A: Eq + PartialEq + Hash + Send + Sync + Clone,
+    G: Mcts<P, A> + Determinable<P, A, G> + Send
+>(game: &G, rng: &R, num_determinizations: usize, budget: &SearchBudget, escape_policy: EscapePolicy, rollout: &dyn RolloutPolicy<P, A, G>, max_cores: usize) -> Result<HashMap<A, ActionStats>, SearchError<G::Error>>
+    where G::Error: Send
+{
     // actions should be the same between all determinizations
     // so, we can pre-calculate the actions, then just copy them into each thread
     let actions = game.actions();
+    if actions.is_empty() {
+        return Err(SearchError::NoActions);
+    }
 
-    let determinization_scores: Arc<Mutex<Determinizations<A, P>>> = Arc::new(Mutex::new(Vec::new()));
-
-    thread::scope(|scope| {
-        for determinization_idx in 0..num_determinizations {
-            {
-                let actions = actions.clone();
+    // all determinizations share one clock
+    let tracker = budget.start();
 
-                let mut rng = clone_and_advance_rng(rng, determinization_idx);
+    let players = game.players();
 
-                let determinization_scores = determinization_scores.clone();
+    // every determinization gets its own stream, split off a copy of the caller's rng
+    let streams = RngStreams::from_rng(&mut rng.clone());
 
-                let current_player = game.current_player();
+    // determinizations are sampled up front, so they don't depend on which thread runs them
+    let determinizations: Vec<(G, R)> = (0..num_determinizations).map(|determinization_idx| {
+        let mut rng: R = streams.stream(determinization_idx as u64);
 
-                let game = game.determine(&mut rng, current_player);
+        let current_player = game.current_player();
 
-                scope.spawn(move || {
-                    let mut action_scores: HashMap<A, HashMap<P, f64>> = HashMap::new();
+        let game = game.determine(&mut rng, current_player);
 
-                    for action in actions.iter() {
-                        let game_after_action = game.apply_action(action.clone(), &mut rng).unwrap();
+        (game, rng)
+    }).collect();
 
-                        let mut scores: HashMap<P, f64> = HashMap::new();
-                        for _simulation_count in 0..num_simulations {
-                            let outcome = random_rollout(&game_after_action, &mut rng);
+    let determinization_scores = run_bounded(determinizations, max_cores, |(game, mut rng)| {
+        let games_after_actions = actions.iter()
+            .map(|action| game.apply_action(action.clone(), &mut rng).map_err(SearchError::IllegalAction))
+            .collect::<Result<Vec<G>, _>>()?;
 
-                            match outcome {
-                                Outcome::Winner(winner) => {
-                                    *scores.entry(winner).or_insert(0f64) += 1f64;
-                                }
-                                Outcome::Winners(_) => unimplemented!(),
-                                Outcome::Escape(_) => {}
-                            }
-                        }
+        let mut totals: Vec<(usize, HashMap<P, f64>)> = actions.iter().map(|_| (0, HashMap::new())).collect();
 
-                        // normalize score values, might be a mistake
-                        let max = scores.values().fold(0f64, |sum, &val| if sum > val { sum } else { val });
-                        scores.iter_mut().for_each(|(_, v)| *v /= max);
+        // each round gives every action one more rollout, the budget is checked between
+        // rollouts so that a deadline or cancellation is noticed quickly
+        let mut rounds = 0;
+        'rounds: loop {
+            for (game_after_action, (num_simulations, scores)) in games_after_actions.iter().zip(totals.iter_mut()) {
+                if tracker.is_exhausted(rounds, 0) {
+                    break 'rounds;
+                }
 
-                        action_scores.insert(action.clone(), scores);
-                    }
+                let outcome = rollout.rollout(game_after_action, &mut rng, None)?;
+                let rewards = escape_policy.resolve(outcome, &players, || rollout.rollout(game_after_action, &mut rng, None))?;
 
-                    determinization_scores.lock().unwrap().push(action_scores);
-                });
+                for (player, reward) in rewards {
+                    *scores.entry(player).or_insert(0f64) += reward;
+                }
+                *num_simulations += 1;
             }
+
+            rounds += 1;
         }
-    });
 
-    game.actions()[0].clone()
+        let action_scores: HashMap<A, (usize, HashMap<P, f64>)> = actions.iter().cloned().zip(totals).map(|(action, (num_simulations, mut scores))| {
+            // turn the summed rewards into average rewards
+            if num_simulations > 0 {
+                scores.iter_mut().for_each(|(_, v)| *v /= num_simulations as f64);
+            }
+
+            (action, (num_simulations, scores))
+        }).collect();
+
+        Ok(action_scores)
+    }).into_iter().collect::<Result<Determinizations<A, P>, SearchError<G::Error>>>()?;
+
+    Ok(aggregate_determinization_scores(&actions, &determinization_scores, &game.current_player()))
 }
 
-fn clone_and_advance_rng<R: Rng + RngCore + Sized + Clone + Send>(rng: &R, delta: usize) -> R {
-    // clone & shadow the rng so each thread has its own copy
-    let mut rng = rng.clone();
+fn aggregate_determinization_scores<P: Eq + Hash, A: Eq + Hash + Clone>(
+    actions: &[A],
+    determinization_scores: &Determinizations<A, P>,
+    perspective_player: &P,
+) -> HashMap<A, ActionStats> {
+    actions.iter().map(|action| {
+        // determinizations which ran out of budget before reaching this action don't count
+        let per_determinization: Vec<&(usize, HashMap<P, f64>)> = determinization_scores.iter()
+            .filter_map(|action_scores| action_scores.get(action))
+            .filter(|(num_simulations, _)| *num_simulations > 0)
+            .collect();
 
-    // advance the RNG by jumping ahead 'determinization_idx' number of jumps before
-    // applying a determinization, that way each determinization is unique
-    for _ in 0..delta {
-        rng.next_u32();
-    }
+        let samples: Vec<f64> = per_determinization.iter()
+            .map(|(_, scores)| scores.get(perspective_player).copied().unwrap_or(0f64))
+            .collect();
 
-    rng
+        let num_simulations = per_determinization.iter().map(|(num_simulations, _)| num_simulations).sum();
+
+        let n = samples.len();
+        let (mean, variance) = if n == 0 {
+            (0f64, 0f64)
+        } else {
+            let mean = samples.iter().sum::<f64>() / n as f64;
+            let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n as f64;
+            (mean, variance)
+        };
+
+        (action.clone(), ActionStats {
+            mean,
+            variance,
+            num_determinizations: n,
+            num_simulations,
+        })
+    }).collect()
 }
 
 #[derive(Clone)]
 pub struct ISMCTSPlayerParams {
     pub num_determinations: usize,
+    /// split between the root parallel trees `generate_graph` searches, one per core
     pub num_simulations_per_action: usize,
+    /// when set, replaces `num_simulations_per_action` as the limit on each move's search, applied
+    /// to each of the trees
+    pub budget: Option<SearchBudget>,
 }
 
 pub struct ISMCTSParams {
@@ -112,16 +238,46 @@ impl Default for ISMCTSParams {
                 ISMCTSPlayerParams {
                     num_determinations: 12,
                     num_simulations_per_action: 100,
+                    budget: None,
                 },
                 ISMCTSPlayerParams {
                     num_determinations: 12,
                     num_simulations_per_action: 100,
+                    budget: None,
                 },
                 ISMCTSPlayerParams {
                     num_determinations: 12,
                     num_simulations_per_action: 100,
+                    budget: None,
                 },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_stats_spread_over_determinizations() {
+        let determinization_scores: Determinizations<char, usize> = vec![
+            HashMap::from([('a', (4, HashMap::from([(0, 1.0), (1, 0.0)]))), ('b', (3, HashMap::from([(0, 0.25)])))]),
+            HashMap::from([('a', (4, HashMap::from([(0, 0.0), (1, 1.0)]))), ('b', (3, HashMap::from([(0, 0.75)])))]),
+            // this determinization ran out of budget before trying `b`, so it doesn't count towards it
+            HashMap::from([('a', (2, HashMap::from([(0, 0.5), (1, 0.5)]))), ('b', (0, HashMap::new()))]),
+        ];
+
+        let stats = aggregate_determinization_scores(&['a', 'b'], &determinization_scores, &0);
+
+        assert_eq!(stats[&'a'].num_determinizations, 3);
+        assert_eq!(stats[&'a'].num_simulations, 10);
+        assert!((stats[&'a'].mean - 0.5).abs() < 1e-12);
+        assert!((stats[&'a'].variance - 1.0 / 6.0).abs() < 1e-12);
+
+        assert_eq!(stats[&'b'].num_determinizations, 2);
+        assert_eq!(stats[&'b'].num_simulations, 6);
+        assert!((stats[&'b'].mean - 0.5).abs() < 1e-12);
+        assert!((stats[&'b'].variance - 0.0625).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use crate::budget::SearchBudget;
+use crate::mcts::{Mcts, MctsConfig, SearchError};
+use crate::mcts::mcts::best_or_first_action;
+use crate::ismcts::Determinable;
+
+/// Single-observer information set MCTS.
+///
+/// The tree is built from the point of view of the observer (the player to move at the root),
+/// so each node stands for an information set: the sequence of actions the observer has seen.
+/// Every iteration samples a fresh determinization of the root, and only the children which are
+/// legal in that determinization are considered for selection.
+///
+/// Of the config, the search uses the exploration constant, the noise and how leaves are valued:
+/// the rollout policy, the evaluator and the escape policy. Priors, RAVE, the expansion policy
+/// and the final move policy are left to `VecTree`.
+pub struct InfoSetTree<P, A, G: Mcts<P, A>> {
+    config: MctsConfig<P, A, G>,
+    observer: P,
+    root_state: G,
+    nodes: InfoSetNodes<P, A>,
+}
+
+impl<
+    P: Eq + PartialEq + Hash + Clone,
+    A: Eq + PartialEq + Clone,
+    G: Mcts<P, A> + Determinable<P, A, G>
+> InfoSetTree<P, A, G> {
+    pub fn from_state(state: G, config: MctsConfig<P, A, G>) -> Self {
+        InfoSetTree {
+            config,
+            observer: state.current_player(),
+            root_state: state,
+            nodes: InfoSetNodes::new(),
+        }
+    }
+
+    pub fn search_n<R: Rng>(&mut self, rng: &mut R, iterations: usize) -> Result<(), SearchError<G::Error>> {
+        for _ in 0..iterations {
+            self.search(rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Searches until the budget runs out, returning the number of iterations run.
+    pub fn search_budget<R: Rng>(&mut self, rng: &mut R, budget: &SearchBudget) -> Result<usize, SearchError<G::Error>> {
+        budget.run(self, Self::num_nodes, |tree| tree.search(rng))
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The most visited action at the root, which is also legal in the actual root state.
+    pub fn best_action(&self) -> Option<A> {
+        self.nodes.most_visited_action(&self.root_state.actions())
+    }
+
+    pub fn search<R: Rng>(&mut self, rng: &mut R) -> Result<(), SearchError<G::Error>> {
+        // sample a state consistent with what the observer knows
+        let mut state = self.root_state.determine(rng, self.observer.clone());
+
+        let mut current_node_idx = 0;
+
+        // track visited nodes for back propagation
+        let mut visited_nodes = vec![current_node_idx];
+
+        loop {
+            if state.outcome().is_some() {
+                break;
+            }
+
+            let actions = state.actions();
+            if actions.is_empty() {
+                return Err(SearchError::NoActions);
+            }
+
+            let untried: Vec<&A> = actions.iter()
+                .filter(|action| self.nodes.child_with_action(current_node_idx, action).is_none())
+                .collect();
+
+            if let Some(action) = untried.choose(rng) {
+                // expand one action which has never been tried from this information set
+                let action = (*action).clone();
+                let mover = state.current_player();
+                state = state.apply_action(action.clone(), rng).map_err(SearchError::IllegalAction)?;
+                current_node_idx = self.nodes.add_node(action, mover, current_node_idx);
+                visited_nodes.push(current_node_idx);
+                break;
+            }
+
+            current_node_idx = self.nodes.select(current_node_idx, &actions, self.config.exploration_constant, self.config.noise, rng).ok_or(SearchError::NoActions)?;
+            let action = self.nodes.action(current_node_idx).clone();
+            state = state.apply_action(action, rng).map_err(SearchError::IllegalAction)?;
+            visited_nodes.push(current_node_idx);
+        }
+
+        let rewards = self.config.leaf_rewards(&state, &self.root_state.players(), rng, None)?;
+        self.nodes.backpropagate(&visited_nodes, &rewards);
+
+        Ok(())
+    }
+}
+
+/// Arena of information set nodes, shared by the single and multiple observer searches.
+pub(crate) struct InfoSetNodes<P, A> {
+    nodes: Vec<InfoSetNode<P, A>>,
+}
+
+impl<P: Eq + Hash + Clone, A: PartialEq + Clone> InfoSetNodes<P, A> {
+    pub(crate) fn new() -> Self {
+        InfoSetNodes {
+            nodes: vec![InfoSetNode::root()],
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(crate) fn action(&self, node_idx: usize) -> &A {
+        self.nodes[node_idx].action.as_ref().expect("non-root node has an action")
+    }
+
+    pub(crate) fn child_with_action(&self, node_idx: usize, action: &A) -> Option<usize> {
+        self.nodes[node_idx].children.iter()
+            .copied()
+            .find(|child_idx| self.nodes[*child_idx].action.as_ref() == Some(action))
+    }
+
+    pub(crate) fn most_visited_action(&self, legal_actions: &[A]) -> Option<A> {
+        let best_child = self.nodes[0].children.iter()
+            .map(|child_idx| &self.nodes[*child_idx])
+            .filter(|child| child.action.as_ref().is_some_and(|a| legal_actions.contains(a)))
+            .fold(None, |acc: Option<&InfoSetNode<P, A>>, child| {
+                match acc {
+                    Some(acc) if acc.num_visits >= child.num_visits => Some(acc),
+                    _ => Some(child),
+                }
+            });
+
+        best_child.and_then(|child| child.action.clone())
+    }
+
+    /// Selects among the children that are legal in the current determinization. Each of those
+    /// children has its availability bumped, so the exploration term only counts the iterations in
+    /// which the child could actually have been picked.
+    pub(crate) fn select<R: Rng>(&mut self, node_idx: usize, legal_actions: &[A], constant_of_exploration: f64, noise: f64, rng: &mut R) -> Option<usize> {
+        let compatible: Vec<usize> = self.nodes[node_idx].children.iter()
+            .copied()
+            .filter(|child_idx| self.nodes[*child_idx].action.as_ref().is_some_and(|a| legal_actions.contains(a)))
+            .collect();
+
+        for child_idx in &compatible {
+            self.nodes[*child_idx].availability += 1.0;
+        }
+
+        let selected = compatible.iter().fold((None, f64::MIN), |acc, child_idx| {
+            let ucb = self.ucb_value(*child_idx, constant_of_exploration, noise, rng);
+            if ucb > acc.1 {
+                (Some(*child_idx), ucb)
+            } else {
+                acc
+            }
+        });
+
+        selected.0
+    }
+
+    fn ucb_value<R: Rng>(&self, node_idx: usize, constant_of_exploration: f64, noise: f64, rng: &mut R) -> f64 {
+        let node = &self.nodes[node_idx];
+
+        if node.num_visits == 0.0 {
+            return f64::MAX;
+        }
+
+        // the value is taken from the perspective of the player who chose this node's action
+        let player_value = node.mover.as_ref()
+            .and_then(|mover| node.value.get(mover))
+            .copied()
+            .unwrap_or(0f64);
+
+        let exploitation_component = player_value / node.num_visits;
+        let exploration_component = constant_of_exploration * (node.availability.ln() / node.num_visits).sqrt();
+        let noise = rng.gen::<f64>() * noise;
+
+        exploitation_component + exploration_component + noise
+    }
+
+    pub(crate) fn add_node(&mut self, action: A, mover: P, parent_idx: usize) -> usize {
+        let next_idx = self.nodes.len();
+
+        self.nodes.push(InfoSetNode {
+            action: Some(action),
+            mover: Some(mover),
+            children: Vec::new(),
+            num_visits: 0.0,
+            availability: 1.0,
+            value: HashMap::new(),
+        });
+        self.nodes[parent_idx].children.push(next_idx);
+
+        next_idx
+    }
+
+    pub(crate) fn backpropagate(&mut self, visited_nodes: &[usize], rewards: &HashMap<P, f64>) {
+        for visited_node_idx in visited_nodes {
+            let node = &mut self.nodes[*visited_node_idx];
+            node.num_visits += 1.0;
+
+            for (player, reward) in rewards {
+                *node.value.entry(player.clone()).or_insert(0f64) += reward;
+            }
+        }
+    }
+}
+
+struct InfoSetNode<P, A> {
+    action: Option<A>,
+    mover: Option<P>,
+    children: Vec<usize>,
+    num_visits: f64,
+    availability: f64,
+    value: HashMap<P, f64>,
+}
+
+impl<P, A> InfoSetNode<P, A> {
+    fn root() -> Self {
+        InfoSetNode {
+            action: None,
+            mover: None,
+            children: Vec::new(),
+            num_visits: 0.0,
+            availability: 1.0,
+            value: HashMap::new(),
+        }
+    }
+}
+
+pub fn so_ismcts<
+    R: Rng + Sized,
+    P: Eq + PartialEq + Hash + Clone,
+    A: Eq + PartialEq + Clone,
+    G: Mcts<P, A> + Determinable<P, A, G>
+>(game: &G, rng: &mut R, num_iterations: usize, config: MctsConfig<P, A, G>) -> Result<A, SearchError<G::Error>> {
+    let mut tree = InfoSetTree::from_state(game.clone(), config);
+
+    tree.search_n(rng, num_iterations)?;
+
+    best_or_first_action(game, tree.best_action())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use crate::mcts::Outcome;
+    use super::*;
+
+    /// Player 0 picks a number below a limit of 1 or 2, which player 0 doesn't know, and wins by
+    /// picking 1.
+    #[derive(Clone, Debug)]
+    struct HiddenLimit {
+        limit: u8,
+        pick: Option<u8>,
+    }
+
+    impl Mcts<usize, u8> for HiddenLimit {
+        type Error = ();
+
+        fn actions(&self) -> Vec<u8> {
+            if self.pick.is_none() { (0..self.limit).collect() } else { vec![] }
+        }
+
+        fn apply_action<R: Rng + Sized>(&self, action: u8, _rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+            Ok(HiddenLimit { limit: self.limit, pick: Some(action) })
+        }
+
+        fn outcome(&self) -> Option<Outcome<usize>> {
+            self.pick.map(|pick| Outcome::Winner(if pick == 1 { 0 } else { 1 }))
+        }
+
+        fn current_player(&self) -> usize {
+            0
+        }
+
+        fn players(&self) -> Vec<usize> {
+            vec![0, 1]
+        }
+    }
+
+    impl Determinable<usize, u8, HiddenLimit> for HiddenLimit {
+        fn determine<R: Rng>(&self, rng: &mut R, _perspective_player: usize) -> HiddenLimit {
+            HiddenLimit { limit: rng.gen_range(1..=2), pick: None }
+        }
+    }
+
+    #[test]
+    fn children_are_only_available_in_determinizations_they_are_legal_in() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let mut tree = InfoSetTree::from_state(HiddenLimit { limit: 2, pick: None }, MctsConfig::default());
+
+        tree.search_n(&mut rng, 1000).unwrap();
+
+        let child = |action: u8| {
+            let child_idx = tree.nodes.child_with_action(0, &action).unwrap();
+            &tree.nodes.nodes[child_idx]
+        };
+        let (pick_0, pick_1) = (child(0), child(1));
+
+        // every iteration but the two which expanded the root selected among its children, where
+        // picking 0 is always legal, and picking 1 only about half the time
+        assert_eq!(pick_0.availability, 1.0 + 998.0);
+        assert!(((pick_1.availability - 1.0) / 998.0 - 0.5).abs() < 0.05);
+
+        // so picking 1 is only ever visited when it is available
+        assert!(pick_1.num_visits <= pick_1.availability);
+        assert_eq!(tree.best_action(), Some(1));
+    }
+}
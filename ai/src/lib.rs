@@ -1,18 +1,71 @@
 mod ismcts;
 mod graph;
 mod mcts;
+mod budget;
+mod parallel;
+mod rng_streams;
 
 pub use mcts::mcts::mcts;
+pub use mcts::mcts::mcts_with_budget;
 pub use mcts::mcts::Mcts;
+pub use mcts::mcts::VecTree;
+pub use mcts::mcts::ChildStats;
+pub use mcts::root_parallel::mcts_root_parallel;
+pub use mcts::root_parallel::RootParallelTrees;
+pub use mcts::shared_tree::mcts_tree_parallel;
+pub use mcts::shared_tree::SharedTree;
+pub use mcts::open_loop::mcts_open_loop;
+pub use mcts::open_loop::OpenLoopTree;
+pub use mcts::transposition::mcts_transposition;
+pub use mcts::transposition::TranspositionTree;
+pub use mcts::transposition::ReplacementPolicy;
+pub use mcts::MctsConfig;
+pub use mcts::FinalMovePolicy;
+pub use mcts::SelectionPolicy;
+pub use mcts::ExpansionPolicy;
+pub use mcts::RaveSchedule;
+pub use mcts::RolloutPolicy;
+pub use mcts::Playout;
+pub use mcts::UniformRollout;
+pub use mcts::EpsilonGreedy;
+pub use mcts::Softmax;
+pub use mcts::DepthLimited;
+pub use mcts::DecisiveMoves;
+pub use mcts::Mast;
+pub use mcts::RolloutGuard;
+pub use mcts::RolloutGuardStats;
+pub use mcts::Cutoff;
+pub use mcts::CutoffFn;
+pub use mcts::Evaluate;
+pub use mcts::EvaluatorFn;
+pub use mcts::Prior;
+pub use mcts::PriorFn;
 pub use mcts::Outcome;
+pub use mcts::EscapePolicy;
+pub use mcts::SearchError;
 pub use mcts::random_rollout;
 
 pub use ismcts::ismcts_mt;
+pub use ismcts::ismcts_mt_stats;
+pub use ismcts::ismcts_mt_with_budget;
+pub use ismcts::ismcts_mt_stats_with_budget;
+pub use ismcts::ActionStats;
+pub use ismcts::so_ismcts;
+pub use ismcts::InfoSetTree;
+pub use ismcts::mo_ismcts;
+pub use ismcts::MultiObserverTrees;
+pub use ismcts::Observable;
 pub use ismcts::Determinable;
 pub use ismcts::ISMCTSParams;
 pub use ismcts::ISMCTSPlayerParams;
 
+pub use rng_streams::RngStreams;
+
+pub use budget::SearchBudget;
+pub use budget::BudgetTracker;
+
 pub use graph::generate_graph;
 pub use graph::Initializer;
 pub use graph::GraphNode;
 pub use graph::GraphEdge;
+pub use graph::GameGraph;
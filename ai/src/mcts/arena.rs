@@ -0,0 +1,34 @@
+use std::collections::{HashMap, VecDeque};
+
+/// A node of a tree stored in a Vec, which links to its parent and children by index.
+pub(crate) trait ArenaNode {
+    fn parent_idx_mut(&mut self) -> &mut Option<usize>;
+    fn children_mut(&mut self) -> &mut Vec<usize>;
+}
+
+/// Keeps only the subtree under `new_root_idx`, moving it to the front of `nodes` with its indices
+/// compacted, so that the new root ends up at index 0. The rest of the tree is dropped.
+pub(crate) fn reroot<N: ArenaNode>(nodes: &mut Vec<N>, new_root_idx: usize) {
+    // walk the subtree breadth first, so every parent is moved before its children
+    let mut old_nodes: Vec<Option<N>> = std::mem::take(nodes).into_iter().map(Some).collect();
+    let mut new_idxs: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([new_root_idx]);
+
+    while let Some(old_idx) = queue.pop_front() {
+        let mut node = old_nodes[old_idx].take().expect("each node is moved once");
+        queue.extend(node.children_mut().iter().copied());
+
+        let parent_idx = node.parent_idx_mut().and_then(|parent_idx| new_idxs.get(&parent_idx).copied());
+        *node.parent_idx_mut() = parent_idx;
+        node.children_mut().clear();
+
+        let new_idx = nodes.len();
+        new_idxs.insert(old_idx, new_idx);
+
+        if let Some(parent_idx) = parent_idx {
+            nodes[parent_idx].children_mut().push(new_idx);
+        }
+
+        nodes.push(node);
+    }
+}
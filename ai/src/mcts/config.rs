@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use rand::RngCore;
+use crate::mcts::{Evaluate, EvaluatorFn, EscapePolicy, Mcts, Prior, PriorFn, RolloutPolicy, SearchError, UniformRollout};
+
+/// How a child is picked while descending the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SelectionPolicy {
+    /// UCB1, `Q + c * sqrt(ln(N) / n)`, where unvisited children are always tried first
+    #[default]
+    Ucb1,
+    /// PUCT, `Q + c * P * sqrt(N) / (1 + n)`, where `P` is the child's prior probability,
+    /// see `MctsConfig::prior`. Unvisited children have a `Q` of 0.
+    Puct,
+}
+
+/// How a node's children are added to the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExpansionPolicy {
+    /// every action is applied and added as a child the first time the node is expanded
+    Eager,
+    /// one untried action is added per visit, the most likely one first, until every action has
+    /// been tried. Only the actions are stored until then, not the states they lead to. With PUCT
+    /// the untried action is only added once its prior outscores the existing children.
+    #[default]
+    Lazy,
+    /// like `Lazy`, but a node which has been visited `n` times has at most `ceil(k * n^alpha)`
+    /// children, for action spaces too large to try every action
+    ProgressiveWidening { k: f64, alpha: f64 },
+}
+
+impl ExpansionPolicy {
+    /// Whether a node with `num_children` children, visited `num_visits` times, may get another one.
+    pub(crate) fn allows_child(&self, num_children: usize, num_visits: f64) -> bool {
+        match self {
+            ExpansionPolicy::Eager => false,
+            ExpansionPolicy::Lazy => true,
+            ExpansionPolicy::ProgressiveWidening { k, alpha } => {
+                let max_children = (k * num_visits.powf(*alpha)).ceil().max(1.0);
+                (num_children as f64) < max_children
+            }
+        }
+    }
+}
+
+/// How much the all-moves-as-first statistics of RAVE count next to a child's own statistics,
+/// as `beta` in `(1 - beta) * Q + beta * AMAF`, for a child with `n` visits and `ñ` AMAF visits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaveSchedule {
+    /// `beta = sqrt(k / (3n + k))`, where `k` is the number of visits at which both count equally
+    Equivalence(f64),
+    /// `beta = ñ / (n + ñ + 4 b² n ñ)`, the minimum MSE schedule, where `b` is the assumed bias
+    /// of the AMAF values
+    MinimumMse(f64),
+}
+
+impl RaveSchedule {
+    pub(crate) fn beta(&self, num_visits: f64, amaf_visits: f64) -> f64 {
+        if amaf_visits == 0.0 {
+            return 0f64;
+        }
+
+        match self {
+            RaveSchedule::Equivalence(k) => (k / (3.0 * num_visits + k)).sqrt(),
+            RaveSchedule::MinimumMse(b) => amaf_visits / (num_visits + amaf_visits + 4.0 * b * b * num_visits * amaf_visits),
+        }
+    }
+}
+
+/// How the action to actually play is picked from the root's children once the search is done.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FinalMovePolicy {
+    /// the child with the most visits
+    MaxVisits,
+    /// the child with the highest average value
+    MaxValue,
+    /// the child with both the most visits and the highest average value,
+    /// falling back to the most visits when no child has both
+    RobustMax,
+    /// the child with the highest lower bound `value - a / sqrt(visits)`
+    SecureChild(f64),
+}
+
+impl FinalMovePolicy {
+    /// Picks one of the children, given the visit count and total value of each of them
+    /// for the player who is choosing. Ties go to the first child, and children which were never
+    /// visited are never picked, as they have no value to go by.
+    pub(crate) fn choose(&self, children: &[(f64, f64)]) -> Option<usize> {
+        let average_value = |(visits, value): (f64, f64)| value / visits;
+
+        match self {
+            FinalMovePolicy::MaxVisits => best_child_by(children, |(visits, _)| visits),
+            FinalMovePolicy::MaxValue => best_child_by(children, average_value),
+            FinalMovePolicy::RobustMax => {
+                let most_visited = best_child_by(children, |(visits, _)| visits);
+                let most_valuable = best_child_by(children, average_value);
+
+                // a child can be both, otherwise the visit count is the more robust choice
+                match (most_visited, most_valuable) {
+                    (Some(visited), Some(valuable)) if children[visited].0 == children[valuable].0 => Some(valuable),
+                    _ => most_visited,
+                }
+            }
+            FinalMovePolicy::SecureChild(a) => best_child_by(children, |child| {
+                average_value(child) - a / child.0.sqrt()
+            }),
+        }
+    }
+}
+
+/// Orders a lazily expanded node's untried actions and their priors for popping off the end, from
+/// least to most likely, with ties going to the earliest action.
+pub(crate) fn order_untried_actions<A>(untried_actions: &mut [(A, f64)]) {
+    untried_actions.sort_by(|a, b| b.1.total_cmp(&a.1));
+    untried_actions.reverse();
+}
+
+fn best_child_by<F: Fn((f64, f64)) -> f64>(children: &[(f64, f64)], score: F) -> Option<usize> {
+    children.iter().enumerate().filter(|(_, (visits, _))| *visits > 0.0).fold(None, |acc: Option<(usize, f64)>, (idx, child)| {
+        let score = score(*child);
+
+        if let Some(acc) = acc {
+            if score <= acc.1 {
+                return Some(acc);
+            }
+        }
+
+        Some((idx, score))
+    }).map(|(idx, _)| idx)
+}
+
+/// Tunable parameters of a `VecTree` search.
+pub struct MctsConfig<P, A, G: Mcts<P, A>> {
+    /// scales the exploration term of the selection policy
+    pub exploration_constant: f64,
+    /// picks the child to descend into
+    pub selection: SelectionPolicy,
+    /// weighs the actions of a node when it is expanded, for `SelectionPolicy::Puct`.
+    /// Without it every action gets the same prior.
+    pub prior: Option<PriorFn<A, G>>,
+    /// plays out the game from a freshly expanded node
+    pub rollout: Arc<dyn RolloutPolicy<P, A, G>>,
+    /// how many children a node gets and when
+    pub expansion: ExpansionPolicy,
+    /// the most actions a single playout may take before the search gives up with an error
+    pub max_rollout_steps: Option<usize>,
+    /// estimates the value of a freshly expanded node for each player, see `with_evaluation`
+    pub evaluator: Option<EvaluatorFn<P, G>>,
+    /// the share of a node's value which comes from the evaluator rather than the rollout,
+    /// from 0 to 1, where 1 skips the rollout altogether. An even mix of 0.5 by default.
+    pub evaluation_weight: f64,
+    /// blends all-moves-as-first statistics into the children's values with the given schedule,
+    /// which speeds up learning in games where the value of an action depends little on when it
+    /// is played, only used by `VecTree`
+    pub rave: Option<RaveSchedule>,
+    /// picks the action returned by `best_action`
+    pub final_move_policy: FinalMovePolicy,
+    /// magnitude of the random noise added to UCB values to break ties
+    pub noise: f64,
+    /// decides what an escaped playout is worth
+    pub escape_policy: EscapePolicy,
+}
+
+impl<P, A, G: Mcts<P, A>> Clone for MctsConfig<P, A, G> {
+    fn clone(&self) -> Self {
+        MctsConfig {
+            exploration_constant: self.exploration_constant,
+            selection: self.selection,
+            prior: self.prior.clone(),
+            rollout: self.rollout.clone(),
+            expansion: self.expansion,
+            max_rollout_steps: self.max_rollout_steps,
+            evaluator: self.evaluator.clone(),
+            evaluation_weight: self.evaluation_weight,
+            rave: self.rave,
+            final_move_policy: self.final_move_policy,
+            noise: self.noise,
+            escape_policy: self.escape_policy,
+        }
+    }
+}
+
+impl<P, A, G: Mcts<P, A>> Default for MctsConfig<P, A, G> {
+    fn default() -> Self {
+        MctsConfig {
+            exploration_constant: 2f64.sqrt(),
+            selection: SelectionPolicy::default(),
+            prior: None,
+            rollout: Arc::new(UniformRollout),
+            expansion: ExpansionPolicy::default(),
+            max_rollout_steps: None,
+            evaluator: None,
+            evaluation_weight: 0.5,
+            rave: None,
+            final_move_policy: FinalMovePolicy::MaxValue,
+            noise: 1e-6,
+            escape_policy: EscapePolicy::default(),
+        }
+    }
+}
+
+impl<P: Eq + Hash + Clone, A, G: Mcts<P, A>> MctsConfig<P, A, G> {
+    /// Values new nodes with the state's `Evaluate` implementation, mixed with the rollout as
+    /// `(1 - evaluation_weight) * rollout + evaluation_weight * evaluation`.
+    pub fn with_evaluation(mut self, evaluation_weight: f64) -> Self where G: Evaluate<P> + 'static, P: 'static {
+        self.evaluator = Some(Arc::new(G::evaluate));
+        self.evaluation_weight = evaluation_weight;
+        self
+    }
+
+    /// Selects children with PUCT, using the state's `Prior` implementation.
+    pub fn with_priors(mut self) -> Self where G: Prior<A> + 'static, A: 'static {
+        self.selection = SelectionPolicy::Puct;
+        self.prior = Some(Arc::new(G::priors));
+        self
+    }
+
+    /// The prior probability of each action, in the same order, summing to 1.
+    pub(crate) fn priors(&self, state: &G, actions: &[A]) -> Vec<f64> {
+        let weights = match &self.prior {
+            Some(prior) => prior(state, actions),
+            None => Vec::new(),
+        };
+
+        let total: f64 = weights.iter().sum();
+
+        // fall back to uniform priors when there are no usable weights
+        if weights.len() != actions.len() || !total.is_finite() || total <= 0f64 {
+            return actions.iter().map(|_| 1.0 / actions.len() as f64).collect();
+        }
+
+        weights.iter().map(|weight| weight / total).collect()
+    }
+
+    /// Whether a node should add its most likely untried action as a child, rather than descend
+    /// into the child with the best selection value, `None` if it has no children to choose from.
+    ///
+    /// UCB1 scores unvisited children above all others, so every action is tried before any is
+    /// tried again. PUCT scores the untried action like an unvisited child, by its prior alone, so
+    /// that actions the priors rule out are passed over without ever being tried.
+    pub(crate) fn prefers_untried_action(&self, prior: f64, parent_visits: f64, best_child_value: Option<f64>) -> bool {
+        match (self.selection, best_child_value) {
+            (SelectionPolicy::Ucb1, _) | (_, None) => true,
+            (SelectionPolicy::Puct, Some(best_child_value)) => self.exploration_constant * prior * parent_visits.sqrt() > best_child_value,
+        }
+    }
+
+    /// The rewards of a freshly reached node: its outcome if the game is over there, otherwise
+    /// a rollout, the evaluator's estimate, or a mix of the two.
+    ///
+    /// With a `trace`, it is filled with the player and action of every step of the rollout.
+    pub(crate) fn leaf_rewards(&self, state: &G, players: &[P], rng: &mut dyn RngCore, mut trace: Option<&mut Vec<(P, A)>>) -> Result<HashMap<P, f64>, SearchError<G::Error>> where A: Clone {
+        let evaluation = match (&self.evaluator, state.outcome()) {
+            (Some(evaluator), None) if self.evaluation_weight > 0.0 => Some(evaluator(state)),
+            _ => None,
+        };
+
+        // nothing left for a rollout to contribute
+        if let Some(evaluation) = &evaluation {
+            if self.evaluation_weight >= 1.0 {
+                return Ok(players.iter().map(|player| (player.clone(), evaluation.get(player).copied().unwrap_or(0f64))).collect());
+            }
+        }
+
+        let mut playout = || match (state.outcome(), trace.as_deref_mut()) {
+            (Some(outcome), _) => Ok(outcome),
+            (None, Some(trace)) => {
+                // only the rollout which is actually used is kept, not the ones retried
+                trace.clear();
+                self.rollout.traced_rollout(state, &mut *rng, self.max_rollout_steps, &mut |state, action| {
+                    trace.push((state.current_player(), action.clone()));
+                })
+            }
+            (None, None) => self.rollout.rollout(state, &mut *rng, self.max_rollout_steps),
+        };
+
+        let outcome = playout()?;
+        let rewards = self.escape_policy.resolve(outcome, players, playout)?;
+
+        Ok(match evaluation {
+            Some(evaluation) => players.iter().map(|player| {
+                let rollout_value = rewards.get(player).copied().unwrap_or(0f64);
+                let evaluated_value = evaluation.get(player).copied().unwrap_or(0f64);
+
+                (player.clone(), (1.0 - self.evaluation_weight) * rollout_value + self.evaluation_weight * evaluated_value)
+            }).collect(),
+            None => rewards,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use rand::SeedableRng;
+    use crate::mcts::test_games::Pile;
+    use super::{FinalMovePolicy, MctsConfig, RaveSchedule};
+
+    // (visits, total value) of each child, the last one never visited
+    const CHILDREN: [(f64, f64); 4] = [(10.0, 6.0), (40.0, 20.0), (2.0, 1.8), (0.0, 0.0)];
+
+    #[test]
+    fn max_visits_picks_the_most_visited_child() {
+        assert_eq!(FinalMovePolicy::MaxVisits.choose(&CHILDREN), Some(1));
+    }
+
+    #[test]
+    fn max_value_picks_the_best_average_among_visited_children() {
+        assert_eq!(FinalMovePolicy::MaxValue.choose(&CHILDREN), Some(2));
+        assert_eq!(FinalMovePolicy::MaxValue.choose(&[(0.0, 0.0), (1.0, 0.2), (0.0, 0.0)]), Some(1));
+    }
+
+    #[test]
+    fn robust_max_needs_a_child_with_both() {
+        assert_eq!(FinalMovePolicy::RobustMax.choose(&CHILDREN), Some(1));
+        assert_eq!(FinalMovePolicy::RobustMax.choose(&[(10.0, 3.0), (40.0, 30.0), (0.0, 0.0)]), Some(1));
+    }
+
+    #[test]
+    fn secure_child_penalizes_few_visits() {
+        assert_eq!(FinalMovePolicy::SecureChild(1.0).choose(&CHILDREN), Some(1));
+        assert_eq!(FinalMovePolicy::SecureChild(0.0).choose(&CHILDREN), Some(2));
+    }
+
+    #[test]
+    fn unvisited_children_are_never_picked() {
+        let unvisited = [(0.0, 0.0), (0.0, 0.0)];
+
+        for policy in [FinalMovePolicy::MaxVisits, FinalMovePolicy::MaxValue, FinalMovePolicy::RobustMax, FinalMovePolicy::SecureChild(1.0)] {
+            assert_eq!(policy.choose(&unvisited), None);
+            assert_eq!(policy.choose(&[(0.0, 0.0), (3.0, 1.0), (0.0, 0.0)]), Some(1));
+        }
+    }
+
+    #[test]
+    fn equivalence_beta_halves_at_k_visits() {
+        let schedule = RaveSchedule::Equivalence(1000.0);
+
+        assert_eq!(schedule.beta(0.0, 5.0), 1.0);
+        // sqrt(1000 / (3 * 1000 + 1000))
+        assert_eq!(schedule.beta(1000.0, 1200.0), 0.5);
+        // sqrt(1000 / (3 * 3000 + 1000))
+        assert!((schedule.beta(3000.0, 3000.0) - 0.1f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn minimum_mse_beta_follows_the_bias() {
+        let schedule = RaveSchedule::MinimumMse(0.5);
+
+        // 4 / (0 + 4 + 0)
+        assert_eq!(schedule.beta(0.0, 4.0), 1.0);
+        // 30 / (10 + 30 + 4 * 0.25 * 10 * 30)
+        assert!((schedule.beta(10.0, 30.0) - 30.0 / 340.0).abs() < 1e-12);
+
+        // without bias, the AMAF visits count as much as the child's own
+        assert_eq!(RaveSchedule::MinimumMse(0.0).beta(10.0, 30.0), 0.75);
+    }
+
+    #[test]
+    fn beta_is_0_without_amaf_visits() {
+        for schedule in [RaveSchedule::Equivalence(1000.0), RaveSchedule::MinimumMse(0.5)] {
+            assert_eq!(schedule.beta(0.0, 0.0), 0.0);
+            assert_eq!(schedule.beta(10.0, 0.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn evaluations_are_mixed_with_rollouts() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+        // player 0 takes the last stone and wins, while the evaluator gives the game to player 1
+        let leaf = Pile::new(1, 0);
+        let mut rewards = |evaluation_weight: f64| {
+            let config = MctsConfig {
+                evaluator: Some(Arc::new(|_: &Pile| HashMap::from([(0, 0.0), (1, 1.0)]))),
+                evaluation_weight,
+                ..MctsConfig::default()
+            };
+            config.leaf_rewards(&leaf, &[0, 1], &mut rng, None).unwrap()
+        };
+
+        assert_eq!(rewards(0.0), HashMap::from([(0, 1.0), (1, 0.0)]));
+        assert_eq!(rewards(0.25), HashMap::from([(0, 0.75), (1, 0.25)]));
+        assert_eq!(rewards(1.0), HashMap::from([(0, 0.0), (1, 1.0)]));
+
+        // setting just the evaluator keeps the rollout
+        let config = MctsConfig { evaluator: Some(Arc::new(|_: &Pile| HashMap::from([(0, 0.0), (1, 1.0)]))), ..MctsConfig::default() };
+        assert_eq!(config.leaf_rewards(&leaf, &[0, 1], &mut rng, None).unwrap(), HashMap::from([(0, 0.5), (1, 0.5)]));
+
+        // and a finished game is never evaluated
+        assert_eq!(config.leaf_rewards(&Pile::new(0, 1), &[0, 1], &mut rng, None).unwrap(), HashMap::from([(0, 1.0), (1, 0.0)]));
+    }
+}
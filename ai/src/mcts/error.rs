@@ -0,0 +1,27 @@
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+#[derive(Debug)]
+pub enum SearchError<E> {
+    /// the game rejected an action it listed as legal
+    IllegalAction(E),
+    /// a state which isn't terminal has no actions to choose from
+    NoActions,
+    /// a playout ran for the given number of steps without reaching a terminal state
+    RolloutLimitExceeded(usize),
+    /// a playout escaped and the `EscapePolicy` didn't allow for it
+    Escaped(String),
+}
+
+impl<E: Debug> Display for SearchError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::IllegalAction(error) => write!(f, "could not apply action: {error:?}"),
+            SearchError::NoActions => write!(f, "no actions available in a non-terminal state"),
+            SearchError::RolloutLimitExceeded(steps) => write!(f, "playout did not finish within {steps} steps"),
+            SearchError::Escaped(reason) => write!(f, "playout escaped: {reason}"),
+        }
+    }
+}
+
+impl<E: Debug> Error for SearchError<E> {}
@@ -0,0 +1,14 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Values a state for each player, usually `Evaluate::evaluate`, but closures may capture e.g. a
+/// loaded model.
+pub type EvaluatorFn<P, G> = Arc<dyn Fn(&G) -> HashMap<P, f64> + Send + Sync>;
+
+/// Estimates how good a game state is for each player without playing it out, e.g. a hand-written
+/// heuristic or a learned value function.
+pub trait Evaluate<P> {
+    /// The value of the state for each player, on the same 0 to 1 scale as `Outcome::rewards`.
+    /// Players which are left out get 0.
+    fn evaluate(&self) -> HashMap<P, f64>;
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::RwLock;
+use rand::RngCore;
+use crate::mcts::{Mcts, Outcome, Playout, RolloutPolicy, SearchError};
+use crate::mcts::rollout_policy::{run_playout, sample_softmax};
+
+/// Move-Average Sampling Technique (MAST), picks actions with probability proportional to
+/// `exp(average / temperature)`, where `average` is the mean reward an action has earned the
+/// player who played it in earlier playouts, wherever in the game it was played. A temperature of
+/// 0 or less plays the best average, ties going to the first action.
+///
+/// The averages are shared by every playout which uses the policy, so they keep being learnt over
+/// the iterations of a search, and from all threads in the parallel searches. Keep using the same
+/// policy for the next move to carry them over, with `decay` to fade out what was learnt earlier,
+/// or `clear` them to start afresh.
+#[derive(Debug)]
+pub struct Mast<A> {
+    pub temperature: f64,
+    /// the average given to actions which haven't been played yet, optimistic by default so that
+    /// they get tried
+    pub initial_value: f64,
+    averages: RwLock<HashMap<A, ActionAverage>>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ActionAverage {
+    num_plays: f64,
+    total_reward: f64,
+}
+
+impl<A: Eq + Hash + Clone> Mast<A> {
+    pub fn new(temperature: f64) -> Self {
+        Mast {
+            temperature,
+            initial_value: 1.0,
+            averages: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The average reward learnt for `action`, `None` if it hasn't been played yet.
+    pub fn average(&self, action: &A) -> Option<f64> {
+        let averages = self.averages.read().unwrap();
+
+        averages.get(action).filter(|average| average.num_plays > 0.0).map(|average| average.total_reward / average.num_plays)
+    }
+
+    /// Scales down the weight of everything learnt so far by `factor`, between 0 and 1, keeping the
+    /// averages themselves, so that new playouts move them faster. Meant to be called between moves.
+    pub fn decay(&self, factor: f64) {
+        let mut averages = self.averages.write().unwrap();
+
+        for average in averages.values_mut() {
+            average.num_plays *= factor;
+            average.total_reward *= factor;
+        }
+    }
+
+    /// Forgets everything learnt so far.
+    pub fn clear(&self) {
+        self.averages.write().unwrap().clear();
+    }
+
+    fn weight(&self, averages: &HashMap<A, ActionAverage>, action: &A) -> f64 {
+        match averages.get(action) {
+            Some(average) if average.num_plays > 0.0 => average.total_reward / average.num_plays,
+            _ => self.initial_value,
+        }
+    }
+
+    /// Adds the rewards of a finished playout to the averages of the actions played in it.
+    fn learn<P: Eq + Hash + Clone>(&self, moves: Vec<(P, A)>, outcome: &Outcome<P>, players: &[P]) {
+        // an escape says nothing about how good the actions were
+        if let Outcome::Escape(_) = outcome {
+            return;
+        }
+
+        let rewards = outcome.rewards(players);
+        let mut averages = self.averages.write().unwrap();
+
+        for (mover, action) in moves {
+            let average = averages.entry(action).or_default();
+            average.num_plays += 1.0;
+            average.total_reward += rewards.get(&mover).copied().unwrap_or(0f64);
+        }
+    }
+}
+
+impl<P: Eq + Hash + Clone, A: Eq + Hash + Clone + Send + Sync, G: Mcts<P, A>> RolloutPolicy<P, A, G> for Mast<A> {
+    fn choose(&self, _state: &G, mut actions: Vec<A>, rng: &mut dyn RngCore) -> A {
+        let weights: Vec<f64> = {
+            let averages = self.averages.read().unwrap();
+            actions.iter().map(|action| self.weight(&averages, action)).collect()
+        };
+
+        let idx = sample_softmax(&weights, self.temperature, rng);
+
+        actions.swap_remove(idx)
+    }
+
+    /// Learns from every playout, including those where a wrapping policy picked some of the actions.
+    fn play_out(&self, state: &G, rng: &mut dyn RngCore, max_steps: Option<usize>, playout: Playout<'_, P, A, G>) -> Result<Outcome<P>, SearchError<G::Error>> {
+        let mut moves: Vec<(P, A)> = Vec::new();
+        let outer_trace = playout.trace;
+
+        let mut trace = |game: &G, action: &A| {
+            moves.push((game.current_player(), action.clone()));
+            outer_trace(game, action);
+        };
+
+        let outcome = run_playout(state, rng, max_steps, Playout {
+            choose: playout.choose,
+            cut_off: playout.cut_off,
+            trace: &mut trace,
+        })?;
+
+        self.learn(moves, &outcome, &state.players());
+
+        Ok(outcome)
+    }
+}
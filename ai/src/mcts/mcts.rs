@@ -3,8 +3,10 @@ use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::PhantomData;
 use rand::{Rng, RngCore};
-use crate::mcts::Outcome;
-use crate::random_rollout;
+use crate::budget::SearchBudget;
+use crate::mcts::arena::{reroot, ArenaNode};
+use crate::mcts::config::order_untried_actions;
+use crate::mcts::{ExpansionPolicy, MctsConfig, Outcome, SearchError, SelectionPolicy};
 
 pub trait Mcts<P, A>: Clone {
     type Error: Debug;
@@ -15,135 +17,393 @@ pub trait Mcts<P, A>: Clone {
 
     fn current_player(&self) -> P;
     fn players(&self) -> Vec<P>;
+
+    /// The possible results of applying `action` with their probabilities, for actions whose
+    /// result is random, such as rolling dice or drawing a card. A `VecTree` then keeps a child
+    /// for every result and averages over them, rather than keeping the one result `apply_action`
+    /// happened to sample.
+    ///
+    /// The default treats every action as deterministic.
+    fn chance_outcomes(&self, _action: &A) -> Option<Vec<(Self, f64)>> {
+        None
+    }
 }
 
 
 pub struct VecTree<P, A, G: Mcts<P, A>> {
-    current_player: P,
+    config: MctsConfig<P, A, G>,
     nodes: Vec<VecTreeNode<P, A, G>>,
     phantom_p: PhantomData<P>,
     phantom_a: PhantomData<A>,
 }
 
-impl<P: Eq + PartialEq + Hash + Clone, A: Clone, G: Mcts<P, A>> VecTree<P, A, G> {
-    pub fn from_state(state: G) -> Self {
+impl<P: Eq + PartialEq + Hash + Clone, A: Clone + PartialEq, G: Mcts<P, A>> VecTree<P, A, G> {
+    pub fn from_state(state: G, config: MctsConfig<P, A, G>) -> Self {
         let mut tree = VecTree {
-            current_player: state.current_player(),
+            config,
             nodes: vec![],
             phantom_p: Default::default(),
             phantom_a: Default::default(),
         };
 
-        tree.add_node(state, None);
+        tree.add_node(state, None, None);
 
         tree
     }
 
-    pub fn search_n<R: Rng>(&mut self, rng: &mut R, iterations: usize) {
+    /// Moves the root of the tree to the child reached by `action`, so the statistics gathered for
+    /// that subtree carry over to the next search. `state` is the actual state after the action was
+    /// played, and replaces the state stored at the new root.
+    ///
+    /// The rest of the tree is dropped and the `nodes` Vec is compacted. If the action was never
+    /// expanded, or led to a chance node, the tree starts over from `state`, as there's no telling
+    /// which of the chance node's results `state` is.
+    pub fn advance(&mut self, action: &A, state: G) {
+        let new_root_idx = self.nodes.first().and_then(|root| {
+            root.children.iter().copied().find(|child_idx| self.nodes[*child_idx].action.as_ref() == Some(action))
+        }).filter(|child_idx| !self.nodes[*child_idx].is_chance);
+
+        let Some(new_root_idx) = new_root_idx else {
+            self.nodes.clear();
+            self.add_node(state, None, None);
+            return;
+        };
+
+        reroot(&mut self.nodes, new_root_idx);
+
+        let root = &mut self.nodes[0];
+        root.state = state;
+        root.action = None;
+        root.mover = None;
+    }
+
+    pub fn search_n<R: Rng>(&mut self, rng: &mut R, iterations: usize) -> Result<(), SearchError<G::Error>> {
         for _ in 0..iterations {
-            self.search(rng);
+            self.search(rng)?;
         }
+
+        Ok(())
+    }
+
+    /// Searches until the budget runs out, returning the number of iterations run.
+    pub fn search_budget<R: Rng>(&mut self, rng: &mut R, budget: &SearchBudget) -> Result<usize, SearchError<G::Error>> {
+        budget.run(self, Self::num_nodes, |tree| tree.search(rng))
     }
 
     pub fn best_action(&self) -> Option<A> {
-        if let Some(root) = self.nodes.first() {
-            let best_child = root.children.iter().enumerate().fold(None, |acc: Option<(usize, f64)>, (action_idx, child_node_idx): (usize, &usize)| {
-                let node = &self.nodes[*child_node_idx];
-                let player_value = if let Some(value) = node.value.get(&self.current_player) {
-                    *value
-                } else {
-                    0f64
-                };
-                let avg_value = player_value / node.num_visits;
+        let children = self.root_children();
+        let summaries: Vec<(f64, f64)> = children.iter().map(|(_, visits, value)| (*visits, *value)).collect();
 
-                if let Some(acc) = acc {
-                    if avg_value > acc.1 {
-                        return Some((action_idx, avg_value));
-                    }
-                }
+        self.config.final_move_policy.choose(&summaries).map(|idx| children[idx].0.clone())
+    }
+
+    /// The statistics of each of the root's children, in the order they were added.
+    pub fn root_stats(&self) -> Vec<ChildStats<P, A>> {
+        let Some(root) = self.nodes.first() else {
+            return Vec::new();
+        };
+
+        root.children.iter().map(|child_idx| {
+            let node = &self.nodes[*child_idx];
 
-                // default
-                Some((action_idx, avg_value))
+            let (average_reward, reward_variance) = if node.num_visits == 0.0 {
+                (0f64, 0f64)
+            } else {
+                let average = node.mover_value() / node.num_visits;
+                let variance = (node.mover_square_value / node.num_visits - average * average).max(0f64);
+                (average, variance)
+            };
+
+            ChildStats {
+                action: node.action.clone().expect("a child has an action"),
+                num_visits: node.num_visits,
+                values: node.value.clone(),
+                average_reward,
+                reward_variance,
+                prior: node.prior,
+                amaf_visits: node.amaf_visits,
+            }
+        }).collect()
+    }
+
+    /// The number of times the root was visited, one per iteration searched since it became the root.
+    pub fn root_visits(&self) -> f64 {
+        self.nodes.first().map(|root| root.num_visits).unwrap_or(0f64)
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The number of actions on the longest path from the root, where the result of a random action
+    /// counts as part of the action.
+    pub fn depth(&self) -> usize {
+        // parents always come before their children in `nodes`, so a single pass sees every parent first
+        let mut depths: Vec<usize> = Vec::with_capacity(self.nodes.len());
+
+        for node in &self.nodes {
+            let depth = match node.parent_idx {
+                Some(parent_idx) if self.nodes[parent_idx].is_chance => depths[parent_idx],
+                Some(parent_idx) => depths[parent_idx] + 1,
+                None => 0,
+            };
+            depths.push(depth);
+        }
+
+        depths.into_iter().max().unwrap_or(0)
+    }
+
+    /// The sequence of actions the search expects to be played, following the most visited child
+    /// from the root, with ties going to the earliest, until a node with no visited children.
+    /// The results of random actions are followed the same way, but don't add to the sequence.
+    pub fn principal_variation(&self) -> Vec<A> {
+        let mut variation = Vec::new();
+        let mut node_idx = 0;
+
+        while let Some(node) = self.nodes.get(node_idx) {
+            let most_visited = node.children.iter().copied().fold(None, |acc: Option<usize>, child_idx| {
+                match acc {
+                    Some(acc) if self.nodes[acc].num_visits >= self.nodes[child_idx].num_visits => Some(acc),
+                    _ => Some(child_idx),
+                }
             });
 
-            if let Some(best_child) = best_child {
-                let actions = root.state.actions();
-                return Some(actions[best_child.0].clone());
+            let Some(child_idx) = most_visited.filter(|child_idx| self.nodes[*child_idx].num_visits > 0.0) else {
+                break;
+            };
+
+            if !node.is_chance {
+                variation.push(self.nodes[child_idx].action.clone().expect("a child has an action"));
             }
+
+            node_idx = child_idx;
         }
 
-        None
+        variation
     }
 
-    pub fn search<R: Rng>(&mut self, rng: &mut R) {
+    pub(crate) fn config(&self) -> &MctsConfig<P, A, G> {
+        &self.config
+    }
+
+    /// The action, visit count and total value for the player to move of each of the root's children.
+    pub(crate) fn root_children(&self) -> Vec<(A, f64, f64)> {
+        let Some(root) = self.nodes.first() else {
+            return Vec::new();
+        };
+
+        root.children.iter().map(|child_idx| {
+            let node = &self.nodes[*child_idx];
+            (node.action.clone().expect("a child has an action"), node.num_visits, node.mover_value())
+        }).collect()
+    }
+
+    pub fn search<R: Rng>(&mut self, rng: &mut R) -> Result<(), SearchError<G::Error>> {
         let mut current_node_idx = 0;
 
         // track visited nodes for back propagation
         let mut visited_nodes = Vec::new();
         visited_nodes.push(current_node_idx);
 
-        // recursively select an optimal node to expand
-        while self.nodes[current_node_idx].is_leaf() == false {
-            current_node_idx = self.select(current_node_idx, rng);
-            visited_nodes.push(current_node_idx);
-        }
+        // recursively select an optimal node to expand, stopping at the first new child
+        while self.nodes[current_node_idx].state.outcome().is_none() {
+            let node = &self.nodes[current_node_idx];
+            let first_visit = node.untried_actions.is_none();
 
-        let result = if let Some(outcome) = self.nodes[current_node_idx].state.outcome() {
-            outcome
-        } else {
-            self.expand(current_node_idx, rng);
+            if first_visit {
+                self.expand(current_node_idx, rng)?;
+            }
 
-            let new_node_idx = self.select(current_node_idx, rng);
-            visited_nodes.push(new_node_idx);
+            let node = &self.nodes[current_node_idx];
+            let next_untried_prior = node.untried_actions.as_ref().and_then(|untried| untried.last()).map(|(_, prior)| *prior);
+            let may_add_child = next_untried_prior.is_some() && (first_visit || self.config.expansion.allows_child(node.children.len(), node.num_visits));
 
-            random_rollout(&self.nodes[new_node_idx].state, rng)
-        };
+            let selected = self.select(current_node_idx, rng);
+
+            if let (true, Some(prior)) = (may_add_child, next_untried_prior) {
+                let best_child_value = selected.as_ref().ok().map(|(_, value)| *value);
 
-        match result {
-            Outcome::Winner(winner_player) => {
-                for visited_node_idx in visited_nodes {
-                    self.nodes[visited_node_idx].num_visits += 1.0;
-                    *self.nodes[visited_node_idx].value.entry(winner_player.clone()).or_insert(0f64) += 1.0;
+                if self.config.prefers_untried_action(prior, node.num_visits, best_child_value) {
+                    let child_idx = self.try_action(current_node_idx, rng)?;
+                    self.visit(child_idx, &mut visited_nodes, rng);
+                    break;
                 }
             }
-            Outcome::Winners(winner_players) => {
-                for visited_node_idx in visited_nodes {
-                    self.nodes[visited_node_idx].num_visits += 1.0;
-                    for winner_player in &winner_players {
-                        *self.nodes[visited_node_idx].value.entry(winner_player.clone()).or_insert(0f64) += 1.0;
-                    }
-                }
+
+            let (child_idx, _) = selected?;
+            current_node_idx = self.visit(child_idx, &mut visited_nodes, rng);
+
+            if first_visit {
+                break;
             }
-            Outcome::Escape(_) => {}
         }
 
+        let playout_node_idx = *visited_nodes.last().expect("at least the root was visited");
+        let mut rollout_trace = Vec::new();
+        let trace = self.config.rave.is_some().then_some(&mut rollout_trace);
+        let rewards = self.config.leaf_rewards(&self.nodes[playout_node_idx].state, &self.nodes[0].state.players(), rng, trace)?;
+
+        if self.config.rave.is_some() {
+            self.update_amaf(&visited_nodes, rollout_trace, &rewards);
+        }
 
+        for visited_node_idx in visited_nodes {
+            let node = &mut self.nodes[visited_node_idx];
+            node.num_visits += 1.0;
+            for (player, reward) in &rewards {
+                *node.value.entry(player.clone()).or_insert(0f64) += reward;
+            }
+
+            let mover_reward = node.mover.as_ref().and_then(|mover| rewards.get(mover)).copied().unwrap_or(0f64);
+            node.mover_square_value += mover_reward * mover_reward;
+        }
+
+        Ok(())
     }
 
-    fn expand<R: Rng>(&mut self, node_idx: usize, rng: &mut R) {
+    /// Updates the all-moves-as-first statistics of the children of every visited node, for each
+    /// action their mover went on to play later in the simulation, in the tree or in the rollout.
+    fn update_amaf(&mut self, visited_nodes: &[usize], rollout_trace: Vec<(P, A)>, rewards: &HashMap<P, f64>) {
+        // the moves of the whole simulation, and for each visited node where its own moves start
+        let mut moves: Vec<(P, A)> = Vec::new();
+        let mut first_moves: Vec<usize> = Vec::with_capacity(visited_nodes.len());
+
+        for node_idx in visited_nodes {
+            let node = &self.nodes[*node_idx];
+
+            // going from a chance node to one of its results isn't a move
+            let is_result = node.parent_idx.is_some_and(|parent_idx| self.nodes[parent_idx].is_chance);
+
+            if let (Some(mover), Some(action), false) = (&node.mover, &node.action, is_result) {
+                moves.push((mover.clone(), action.clone()));
+            }
+
+            first_moves.push(moves.len());
+        }
+
+        moves.extend(rollout_trace);
+
+        for (node_idx, first_move) in visited_nodes.iter().zip(first_moves) {
+            if self.nodes[*node_idx].is_chance {
+                continue;
+            }
+
+            let later_moves = &moves[first_move..];
+
+            for child_pos in 0..self.nodes[*node_idx].children.len() {
+                let child_idx = self.nodes[*node_idx].children[child_pos];
+                let child = &mut self.nodes[child_idx];
+
+                let (Some(mover), Some(action)) = (&child.mover, &child.action) else {
+                    continue;
+                };
+
+                if later_moves.iter().any(|(player, played)| player == mover && played == action) {
+                    child.amaf_visits += 1.0;
+                    child.amaf_value += rewards.get(mover).copied().unwrap_or(0f64);
+                }
+            }
+        }
+    }
+
+    fn expand<R: Rng>(&mut self, node_idx: usize, rng: &mut R) -> Result<(), SearchError<G::Error>> {
         let actions = {
             let node = &self.nodes[node_idx];
             node.state.actions()
         };
 
-        if actions.len() == 0 {
-            panic!("no actions to expand into")
+        if actions.is_empty() {
+            return Err(SearchError::NoActions);
         }
 
-        for action in actions {
-            let node = &self.nodes[node_idx];
-            let state = node.state.apply_action(action.clone(), rng).unwrap();
-            self.add_node(state, Some(node_idx));
+        let priors = self.config.priors(&self.nodes[node_idx].state, &actions);
+        let mut untried_actions: Vec<(A, f64)> = actions.into_iter().zip(priors).collect();
+
+        if self.config.expansion == ExpansionPolicy::Eager {
+            for (action, prior) in untried_actions {
+                self.add_child(node_idx, action, prior, rng)?;
+            }
+
+            self.nodes[node_idx].untried_actions = Some(Vec::new());
+            return Ok(());
         }
+
+        order_untried_actions(&mut untried_actions);
+        self.nodes[node_idx].untried_actions = Some(untried_actions);
+
+        Ok(())
+    }
+
+    /// Adds the most likely untried action of the node as a new child, returning its index.
+    fn try_action<R: Rng>(&mut self, node_idx: usize, rng: &mut R) -> Result<usize, SearchError<G::Error>> {
+        let node = &mut self.nodes[node_idx];
+        let (action, prior) = node.untried_actions.as_mut().and_then(|untried| untried.pop()).ok_or(SearchError::NoActions)?;
+
+        self.add_child(node_idx, action, prior, rng)
     }
 
-    fn select<R: Rng>(&self, node_idx: usize, rng: &mut R) -> usize {
+    /// Adds the child reached by `action`, which is a chance node with a child per possible result
+    /// if the action's result is random.
+    fn add_child<R: Rng>(&mut self, node_idx: usize, action: A, prior: f64, rng: &mut R) -> Result<usize, SearchError<G::Error>> {
+        let state = &self.nodes[node_idx].state;
+
+        let child_idx = match state.chance_outcomes(&action) {
+            Some(outcomes) if !outcomes.is_empty() => {
+                // the chance node stands for the moment after the action, before its result is known
+                let chance_idx = self.add_node(state.clone(), Some(action.clone()), Some(node_idx));
+                self.nodes[chance_idx].is_chance = true;
+
+                for (outcome_state, probability) in outcomes {
+                    let outcome_idx = self.add_node(outcome_state, Some(action.clone()), Some(chance_idx));
+                    self.nodes[outcome_idx].prior = probability;
+                }
+
+                chance_idx
+            }
+            _ => {
+                let state = state.apply_action(action.clone(), rng).map_err(SearchError::IllegalAction)?;
+                self.add_node(state, Some(action), Some(node_idx))
+            }
+        };
+
+        self.nodes[child_idx].prior = prior;
+
+        Ok(child_idx)
+    }
+
+    /// Records a visit to the node, and for a chance node also to one of its results, sampled by
+    /// probability. Returns the index of the last node visited.
+    fn visit<R: Rng>(&self, node_idx: usize, visited_nodes: &mut Vec<usize>, rng: &mut R) -> usize {
+        visited_nodes.push(node_idx);
+
         let node = &self.nodes[node_idx];
+        if !node.is_chance {
+            return node_idx;
+        }
+
+        let total: f64 = node.children.iter().map(|child_idx| self.nodes[*child_idx].prior).sum();
+        let mut threshold = rng.gen::<f64>() * total;
 
-        let constant_of_exploration = 2f64.sqrt();
+        let outcome_idx = node.children.iter().copied().find(|child_idx| {
+            threshold -= self.nodes[*child_idx].prior;
+            threshold <= 0f64
+        }).unwrap_or(*node.children.last().expect("a chance node has results"));
+
+        visited_nodes.push(outcome_idx);
+        outcome_idx
+    }
+
+    /// The child with the best selection value, with that value.
+    fn select<R: Rng>(&self, node_idx: usize, rng: &mut R) -> Result<(usize, f64), SearchError<G::Error>> {
+        let node = &self.nodes[node_idx];
+
+        let constant_of_exploration = self.config.exploration_constant;
 
         let selected = node.children.iter().fold((None, f64::MIN), |acc, child_idx| {
-            let ucb = self.ucbt_value(*child_idx, constant_of_exploration, rng);
+            let ucb = match self.config.selection {
+                SelectionPolicy::Ucb1 => self.ucbt_value(*child_idx, constant_of_exploration, rng),
+                SelectionPolicy::Puct => self.puct_value(*child_idx, constant_of_exploration, rng),
+            };
             if ucb > acc.1 {
                 (Some(*child_idx), ucb)
             } else {
@@ -151,11 +411,7 @@ impl<P: Eq + PartialEq + Hash + Clone, A: Clone, G: Mcts<P, A>> VecTree<P, A, G>
             }
         });
 
-        if let Some(selected) = selected.0 {
-            selected
-        } else {
-            panic!("could not select")
-        }
+        selected.0.map(|child_idx| (child_idx, selected.1)).ok_or(SearchError::NoActions)
     }
 
     fn ucbt_value<R: Rng>(&self, node_idx: usize, constant_of_exploration: f64, rng: &mut R) -> f64 {
@@ -165,25 +421,54 @@ impl<P: Eq + PartialEq + Hash + Clone, A: Clone, G: Mcts<P, A>> VecTree<P, A, G>
             return f64::MAX;
         }
 
-        let player_value = if let Some(value) = node.value.get(&self.current_player) {
-            *value
-        } else {
-            0f64
-        };
+        // each player picks the moves that are best for themselves, so the value is
+        // taken from the perspective of the player who chose this node's action
+        let player_value = node.mover_value();
 
         // first component of UCB1 formula corresponds to exploitation
         // as it is high for moves with a high averate win ratio
         // this is the average reward, or win ratio, of the node
-        let exploitation_component = player_value / node.num_visits;
+        let exploitation_component = self.blend_amaf(node, player_value / node.num_visits);
 
         // the second component corresponds to exploration
         let parent_vists = self.parent_visits(node);
         let exploration_component = constant_of_exploration * ((parent_vists + 1.0).ln() / node.num_visits).sqrt();
-        let noise = rng.next_u32() as f64 * 1e-6;
+        let noise = rng.gen::<f64>() * self.config.noise;
+
+        exploitation_component + exploration_component + noise
+    }
+
+    fn puct_value<R: Rng>(&self, node_idx: usize, constant_of_exploration: f64, rng: &mut R) -> f64 {
+        let node = &self.nodes[node_idx];
+
+        let exploitation_component = if node.num_visits == 0.0 {
+            0f64
+        } else {
+            self.blend_amaf(node, node.mover_value() / node.num_visits)
+        };
+
+        // the prior steers exploration towards the actions it favours, fading as the node is visited
+        let parent_visits = self.parent_visits(node);
+        let exploration_component = constant_of_exploration * node.prior * parent_visits.sqrt() / (1.0 + node.num_visits);
+        let noise = rng.gen::<f64>() * self.config.noise;
 
         exploitation_component + exploration_component + noise
     }
 
+    /// Blends the node's average value with its all-moves-as-first value when RAVE is on.
+    fn blend_amaf(&self, node: &VecTreeNode<P, A, G>, average_value: f64) -> f64 {
+        let Some(schedule) = self.config.rave else {
+            return average_value;
+        };
+
+        let beta = schedule.beta(node.num_visits, node.amaf_visits);
+        if beta == 0.0 {
+            return average_value;
+        }
+
+        (1.0 - beta) * average_value + beta * node.amaf_value / node.amaf_visits
+    }
+
     fn parent_visits(&self, node: &VecTreeNode<P, A, G>) -> f64 {
         if let Some(parent_idx) = node.parent_idx {
             let parent = &self.nodes[parent_idx];
@@ -193,11 +478,12 @@ impl<P: Eq + PartialEq + Hash + Clone, A: Clone, G: Mcts<P, A>> VecTree<P, A, G>
         }
     }
 
-    fn add_node(&mut self, state: G, parent_idx: Option<usize>) -> usize {
+    fn add_node(&mut self, state: G, action: Option<A>, parent_idx: Option<usize>) -> usize {
         let next_idx = self.nodes.len();
 
         let node = if let Some(parent_idx) = parent_idx {
-            let node = VecTreeNode::from_state_with_parent_idx(state, parent_idx);
+            let mover = self.nodes[parent_idx].state.current_player();
+            let node = VecTreeNode::from_state_with_parent_idx(state, action, mover, parent_idx);
             self.nodes[parent_idx].children.push(next_idx);
             node
         } else {
@@ -214,37 +500,110 @@ pub struct VecTreeNode<P, A, G: Mcts<P, A>> {
     num_visits: f64,
     value: HashMap<P, f64>,
     state: G,
+    action: Option<A>,
+    mover: Option<P>,
+    /// the prior probability of this node's action, given when its parent was expanded,
+    /// or for the results of a chance node, the probability of the result
+    prior: f64,
+    /// all-moves-as-first visits, the simulations in which this node's mover played its action
+    /// anywhere after the parent
+    amaf_visits: f64,
+    /// sum of the squared rewards of the mover, for the variance of its reward
+    mover_square_value: f64,
+    /// total reward of the mover over the all-moves-as-first visits
+    amaf_value: f64,
+    /// whether the node's children are the possible results of its action rather than actions
+    is_chance: bool,
+    /// actions which haven't been added as children yet with their priors, `None` until expanded
+    untried_actions: Option<Vec<(A, f64)>>,
     parent_idx: Option<usize>,
     children: Vec<usize>,
-    phantom_a: PhantomData<A>,
 }
 
 
-impl<P, A: Clone, G: Mcts<P, A>> VecTreeNode<P, A, G> {
+impl<P: Eq + Hash, A: Clone, G: Mcts<P, A>> VecTreeNode<P, A, G> {
     fn from_state(state: G) -> Self {
         VecTreeNode {
             num_visits: 0.0,
             value: HashMap::new(),
             state: state,
+            action: None,
+            mover: None,
+            prior: 1.0,
+            mover_square_value: 0.0,
+            amaf_visits: 0.0,
+            amaf_value: 0.0,
+            is_chance: false,
+            untried_actions: None,
             parent_idx: None,
             children: Vec::new(),
-            phantom_a: Default::default(),
         }
     }
 
-    fn from_state_with_parent_idx(state: G, parent: usize) -> Self {
+    fn from_state_with_parent_idx(state: G, action: Option<A>, mover: P, parent: usize) -> Self {
         VecTreeNode {
             num_visits: 0.0,
             value: HashMap::new(),
             state: state,
+            action,
+            mover: Some(mover),
+            prior: 1.0,
+            mover_square_value: 0.0,
+            amaf_visits: 0.0,
+            amaf_value: 0.0,
+            is_chance: false,
+            untried_actions: None,
             parent_idx: Some(parent),
             children: Vec::new(),
-            phantom_a: Default::default(),
         }
     }
 
-    fn is_leaf(&self) -> bool {
-        self.children.len() == 0
+    /// The total value of this node for the player who chose the action leading to it.
+    fn mover_value(&self) -> f64 {
+        self.mover.as_ref().and_then(|mover| self.value.get(mover)).copied().unwrap_or(0f64)
+    }
+}
+
+impl<P, A, G: Mcts<P, A>> ArenaNode for VecTreeNode<P, A, G> {
+    fn parent_idx_mut(&mut self) -> &mut Option<usize> {
+        &mut self.parent_idx
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<usize> {
+        &mut self.children
+    }
+}
+
+/// What a search found out about one of the root's children, see `VecTree::root_stats`.
+#[derive(Clone, Debug)]
+pub struct ChildStats<P, A> {
+    pub action: A,
+    pub num_visits: f64,
+    /// total reward of each player over the child's visits
+    pub values: HashMap<P, f64>,
+    /// mean reward of the player who chose the action, the value `best_action` ranks children by
+    pub average_reward: f64,
+    /// population variance of the rewards of the player who chose the action
+    pub reward_variance: f64,
+    /// the prior probability of the action, 1 unless priors are used
+    pub prior: f64,
+    /// the number of simulations where the player who chose the action played it at any point
+    /// after the root, 0 unless RAVE is used
+    pub amaf_visits: f64,
+}
+
+impl<P, A> ChildStats<P, A> {
+    /// The normal approximation confidence interval around the average reward, `z` standard errors
+    /// either side, e.g. 1.96 for 95%. It is only meaningful once the child has a fair number of
+    /// visits, and is unbounded with none.
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        if self.num_visits == 0.0 {
+            return (f64::NEG_INFINITY, f64::INFINITY);
+        }
+
+        let margin = z * (self.reward_variance / self.num_visits).sqrt();
+
+        (self.average_reward - margin, self.average_reward + margin)
     }
 }
 
@@ -253,11 +612,337 @@ pub fn mcts<
     P: Eq + PartialEq + Hash + Clone + Send,
     A: Eq + PartialEq + Hash + Clone + Debug,
     G: Mcts<P, A>
->(game: &G, rng: &mut R, num_simulations: usize) -> A {
-    let mut tree = VecTree::from_state(game.clone());
+>(game: &G, rng: &mut R, num_simulations: usize, config: MctsConfig<P, A, G>) -> Result<A, SearchError<G::Error>> {
+    // for repeated searches over the course of a game, keep a VecTree around and `advance` it instead
+    let mut tree = VecTree::from_state(game.clone(), config);
+
+    tree.search_n(rng, num_simulations)?;
+
+    best_or_first_action(game, tree.best_action())
+}
+
+/// Like `mcts`, but searches until the budget runs out instead of for a fixed number of iterations.
+/// A budget which is spent before the search starts gets the first legal action.
+pub fn mcts_with_budget<
+    R: Rng + RngCore + Sized,
+    P: Eq + PartialEq + Hash + Clone + Send,
+    A: Eq + PartialEq + Hash + Clone + Debug,
+    G: Mcts<P, A>
+>(game: &G, rng: &mut R, budget: &SearchBudget, config: MctsConfig<P, A, G>) -> Result<A, SearchError<G::Error>> {
+    let mut tree = VecTree::from_state(game.clone(), config);
+
+    tree.search_budget(rng, budget)?;
+
+    best_or_first_action(game, tree.best_action())
+}
+
+/// The action a search picked, or if it ran out of budget before running a single iteration, e.g.
+/// because it was cancelled right away, the first legal action.
+pub(crate) fn best_or_first_action<P, A, G: Mcts<P, A>>(game: &G, best_action: Option<A>) -> Result<A, SearchError<G::Error>> {
+    best_action.or_else(|| game.actions().into_iter().next()).ok_or(SearchError::NoActions)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use crate::mcts::RaveSchedule;
+    use crate::mcts::test_games::Pile;
+    use super::*;
+
+    /// Player 0 sends the game left or right. Left, player 1 picks whether they or player 2 win. Right,
+    /// player 2 picks whether player 0 or player 1 wins, which makes no difference to them.
+    #[derive(Clone, Debug)]
+    struct Kingmaker {
+        side: Option<u8>,
+        winner: Option<usize>,
+    }
+
+    impl Mcts<usize, u8> for Kingmaker {
+        type Error = ();
+
+        fn actions(&self) -> Vec<u8> {
+            match (self.side, self.winner) {
+                (_, Some(_)) => vec![],
+                (None, None) => vec![0, 1],
+                (Some(0), None) => vec![1, 2],
+                (Some(_), None) => vec![0, 1],
+            }
+        }
+
+        fn apply_action<R: Rng + Sized>(&self, action: u8, _rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+            match self.side {
+                None => Ok(Kingmaker { side: Some(action), winner: None }),
+                Some(side) => Ok(Kingmaker { side: Some(side), winner: Some(action as usize) }),
+            }
+        }
+
+        fn outcome(&self) -> Option<Outcome<usize>> {
+            self.winner.map(Outcome::Winner)
+        }
+
+        fn current_player(&self) -> usize {
+            match self.side {
+                None => 0,
+                Some(side) => 1 + side as usize,
+            }
+        }
+
+        fn players(&self) -> Vec<usize> {
+            vec![0, 1, 2]
+        }
+    }
+
+    /// Player 0 rolls a loaded three-sided die, and wins unless it comes up 1.
+    #[derive(Clone, Debug)]
+    struct LoadedDie {
+        face: Option<u8>,
+    }
+
+    const FACES: [(u8, f64); 3] = [(1, 0.5), (2, 0.3), (3, 0.2)];
+
+    impl Mcts<usize, u8> for LoadedDie {
+        type Error = ();
+
+        fn actions(&self) -> Vec<u8> {
+            if self.face.is_none() { vec![0] } else { vec![] }
+        }
+
+        fn apply_action<R: Rng + Sized>(&self, _action: u8, rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+            Ok(LoadedDie { face: Some(rng.gen_range(1..=3)) })
+        }
+
+        fn outcome(&self) -> Option<Outcome<usize>> {
+            self.face.map(|face| Outcome::Winner(if face == 1 { 1 } else { 0 }))
+        }
+
+        fn current_player(&self) -> usize {
+            0
+        }
+
+        fn players(&self) -> Vec<usize> {
+            vec![0, 1]
+        }
+
+        fn chance_outcomes(&self, _action: &u8) -> Option<Vec<(Self, f64)>> {
+            Some(FACES.iter().map(|(face, probability)| (LoadedDie { face: Some(*face) }, *probability)).collect())
+        }
+    }
+
+    /// Player 0 rolls a fair three-sided die with action 0, then calls a face from 0 to 2, and wins
+    /// if they called the roll. Calling 0 is the same move as the roll, as far as the actions go.
+    #[derive(Clone, Debug)]
+    struct RollAndCall {
+        face: Option<u8>,
+        call: Option<u8>,
+    }
+
+    impl Mcts<usize, u8> for RollAndCall {
+        type Error = ();
+
+        fn actions(&self) -> Vec<u8> {
+            match (self.face, self.call) {
+                (None, _) => vec![0],
+                (Some(_), None) => vec![0, 1, 2],
+                (Some(_), Some(_)) => vec![],
+            }
+        }
+
+        fn apply_action<R: Rng + Sized>(&self, action: u8, rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+            match self.face {
+                None => Ok(RollAndCall { face: Some(rng.gen_range(0..=2)), call: None }),
+                Some(face) => Ok(RollAndCall { face: Some(face), call: Some(action) }),
+            }
+        }
+
+        fn outcome(&self) -> Option<Outcome<usize>> {
+            self.call.map(|call| Outcome::Winner(if Some(call) == self.face { 0 } else { 1 }))
+        }
+
+        fn current_player(&self) -> usize {
+            0
+        }
+
+        fn players(&self) -> Vec<usize> {
+            vec![0, 1]
+        }
+
+        fn chance_outcomes(&self, _action: &u8) -> Option<Vec<(Self, f64)>> {
+            if self.face.is_some() {
+                return None;
+            }
+
+            Some((0..=2).map(|face| (RollAndCall { face: Some(face), call: None }, 1.0 / 3.0)).collect())
+        }
+    }
+
+    /// Player 0 picks a number below 100, and wins with one of 90 or more.
+    #[derive(Clone, Debug)]
+    struct PickHigh {
+        pick: Option<u8>,
+    }
+
+    impl Mcts<usize, u8> for PickHigh {
+        type Error = ();
+
+        fn actions(&self) -> Vec<u8> {
+            if self.pick.is_none() { (0..100).collect() } else { vec![] }
+        }
+
+        fn apply_action<R: Rng + Sized>(&self, action: u8, _rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+            Ok(PickHigh { pick: Some(action) })
+        }
+
+        fn outcome(&self) -> Option<Outcome<usize>> {
+            self.pick.map(|pick| Outcome::Winner(if pick >= 90 { 0 } else { 1 }))
+        }
 
-    // damn waste of a populated tree after this function closes
-    tree.search_n(rng, num_simulations);
+        fn current_player(&self) -> usize {
+            0
+        }
+
+        fn players(&self) -> Vec<usize> {
+            vec![0, 1]
+        }
+    }
+
+    fn subtree_size<P, A, G: Mcts<P, A>>(nodes: &[VecTreeNode<P, A, G>], node_idx: usize) -> usize {
+        1 + nodes[node_idx].children.iter().map(|child_idx| subtree_size(nodes, *child_idx)).sum::<usize>()
+    }
+
+    #[test]
+    fn advance_keeps_the_played_subtree() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let game = Pile::new(10, 0);
+
+        let mut tree = VecTree::from_state(game.clone(), MctsConfig::default());
+        tree.search_n(&mut rng, 500).unwrap();
+
+        let played_idx = tree.nodes[0].children[0];
+        let played = tree.nodes[played_idx].action.unwrap();
+        let num_visits = tree.nodes[played_idx].num_visits;
+        let num_nodes = subtree_size(&tree.nodes, played_idx);
+
+        tree.advance(&played, game.apply_action(played, &mut rng).unwrap());
+
+        // only the subtree is kept, moved to the front of `nodes` with its statistics
+        assert_eq!(tree.nodes.len(), num_nodes);
+        assert_eq!(tree.nodes[0].num_visits, num_visits);
+        assert!(tree.nodes[0].action.is_none() && tree.nodes[0].parent_idx.is_none());
+
+        // and every index points at the right node of the compacted Vec
+        for (node_idx, node) in tree.nodes.iter().enumerate().skip(1) {
+            let parent_idx = node.parent_idx.unwrap();
+            assert!(parent_idx < node_idx);
+            assert!(tree.nodes[parent_idx].children.contains(&node_idx));
+        }
+
+        tree.search_n(&mut rng, 100).unwrap();
+        assert_eq!(tree.nodes[0].num_visits, num_visits + 100.0);
+
+        // an action which was never expanded starts over
+        let mut tree = VecTree::from_state(game.clone(), MctsConfig::default());
+        tree.advance(&1, game.apply_action(1, &mut rng).unwrap());
+        assert_eq!(tree.nodes.len(), 1);
+    }
 
-    tree.best_action().expect("an action")
+    #[test]
+    fn three_players_are_valued_from_their_own_perspective() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let mut tree = VecTree::from_state(Kingmaker { side: None, winner: None }, MctsConfig::default());
+
+        tree.search_n(&mut rng, 2000).unwrap();
+
+        let child = |action: u8| {
+            let child_idx = tree.nodes[0].children.iter().find(|child_idx| tree.nodes[**child_idx].action == Some(action));
+            &tree.nodes[*child_idx.unwrap()]
+        };
+        let (left, right) = (child(0), child(1));
+
+        // left, player 1 makes themselves win
+        assert!(left.value[&1] / left.num_visits > 0.9);
+        assert!(left.mover_value() / left.num_visits < 0.1);
+
+        // right, player 2 has nothing to gain, so player 0 still wins about half the time
+        assert!((right.mover_value() / right.num_visits - 0.5).abs() < 0.1);
+        assert_eq!(right.value[&2], 0.0);
+
+        assert_eq!(tree.best_action(), Some(1));
+    }
+
+    #[test]
+    fn chance_results_are_visited_by_probability() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let mut tree = VecTree::from_state(LoadedDie { face: None }, MctsConfig::default());
+
+        tree.search_n(&mut rng, 2000).unwrap();
+
+        let chance_idx = tree.nodes[0].children[0];
+        assert!(tree.nodes[chance_idx].is_chance);
+
+        let chance_visits = tree.nodes[chance_idx].num_visits;
+        for (result_idx, (face, probability)) in tree.nodes[chance_idx].children.iter().zip(FACES) {
+            let result = &tree.nodes[*result_idx];
+            assert_eq!(result.state.face, Some(face));
+            assert!((result.num_visits / chance_visits - probability).abs() < 0.05);
+        }
+
+        // there's no telling which result the actual state is, so the tree starts over
+        tree.advance(&0, LoadedDie { face: Some(2) });
+        assert_eq!(tree.nodes.len(), 1);
+    }
+
+    #[test]
+    fn chance_results_are_not_moves_for_rave() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let config = MctsConfig { rave: Some(RaveSchedule::Equivalence(100.0)), ..MctsConfig::default() };
+        let mut tree = VecTree::from_state(RollAndCall { face: None, call: None }, config);
+
+        tree.search_n(&mut rng, 500).unwrap();
+
+        let chance_idx = tree.nodes[0].children[0];
+        let chance = &tree.nodes[chance_idx];
+        assert!(chance.is_chance);
+        assert_eq!(chance.amaf_visits, chance.num_visits);
+
+        for result_idx in &chance.children {
+            let result = &tree.nodes[*result_idx];
+
+            // the results aren't moves, so calling 0 after the roll doesn't count as rolling
+            assert_eq!(result.amaf_visits, 0.0);
+
+            // the call goes to the result's children instead, at most one per simulation
+            let calls: f64 = result.children.iter().map(|call_idx| tree.nodes[*call_idx].amaf_visits).sum();
+            assert!(calls > 0.0 && calls <= result.num_visits);
+        }
+    }
+
+    #[test]
+    fn lazy_expansion_stores_one_child_per_iteration() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let mut tree = VecTree::from_state(PickHigh { pick: None }, MctsConfig::default());
+
+        tree.search_n(&mut rng, 1).unwrap();
+
+        // only the state of the child played out is stored, the rest are left as actions
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.nodes[0].children.len(), 1);
+        assert_eq!(tree.nodes[0].untried_actions.as_ref().map(|untried| untried.len()), Some(99));
+    }
+
+    #[test]
+    fn progressive_widening_bounds_the_number_of_children() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let config = MctsConfig { expansion: ExpansionPolicy::ProgressiveWidening { k: 1.0, alpha: 0.5 }, ..MctsConfig::default() };
+        let mut tree = VecTree::from_state(PickHigh { pick: None }, config);
+
+        for _ in 0..400 {
+            tree.search_n(&mut rng, 1).unwrap();
+
+            let root = &tree.nodes[0];
+            assert!(root.children.len() as f64 <= root.num_visits.sqrt().ceil());
+        }
+
+        // 400 visits allow 20 children, out of the 100 actions
+        assert_eq!(tree.nodes[0].children.len(), 20);
+    }
 }
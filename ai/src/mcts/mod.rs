@@ -1,9 +1,30 @@
 pub mod random_rollout;
 pub mod mcts;
+pub mod config;
+pub mod root_parallel;
+pub mod shared_tree;
+pub mod open_loop;
+pub mod transposition;
+pub mod rollout_policy;
+pub mod mast;
+pub mod rollout_guard;
+pub mod evaluate;
+pub mod prior;
 mod action;
+mod arena;
 mod termination;
+mod error;
+#[cfg(test)]
+mod test_games;
 
 pub use random_rollout::random_rollout;
 pub use mcts::Mcts;
-pub use termination::Outcome;
+pub use config::{ExpansionPolicy, FinalMovePolicy, MctsConfig, RaveSchedule, SelectionPolicy};
+pub use rollout_policy::{DecisiveMoves, DepthLimited, EpsilonGreedy, Playout, RolloutPolicy, Softmax, UniformRollout};
+pub use mast::Mast;
+pub use rollout_guard::{Cutoff, CutoffFn, RolloutGuard, RolloutGuardStats};
+pub use termination::{EscapePolicy, Outcome};
+pub use error::SearchError;
+pub use evaluate::{Evaluate, EvaluatorFn};
+pub use prior::{Prior, PriorFn};
 
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use rand::{Rng, RngCore};
+use crate::budget::SearchBudget;
+use crate::mcts::arena::{reroot, ArenaNode};
+use crate::mcts::mcts::best_or_first_action;
+use crate::mcts::{ExpansionPolicy, Mcts, MctsConfig, SearchError, SelectionPolicy};
+
+/// Open loop MCTS.
+///
+/// Nodes stand for sequences of actions rather than states, only the root state is stored. Every
+/// iteration replays the actions from the root, so random results are sampled afresh each time and
+/// the statistics average over them, and states never have to be cloned into the tree.
+///
+/// As the legal actions can differ between replays, children are only considered while their
+/// action is legal, and UCB uses how often a child was available in place of the parent's visits.
+/// Likewise, when a random result decides who moves next, each player who gets to move after the
+/// same actions has children of their own.
+pub struct OpenLoopTree<P, A, G: Mcts<P, A>> {
+    config: MctsConfig<P, A, G>,
+    root_state: G,
+    nodes: Vec<OpenLoopNode<P, A>>,
+}
+
+impl<P: Eq + PartialEq + Hash + Clone, A: Clone + PartialEq, G: Mcts<P, A>> OpenLoopTree<P, A, G> {
+    pub fn from_state(state: G, config: MctsConfig<P, A, G>) -> Self {
+        OpenLoopTree {
+            config,
+            root_state: state,
+            nodes: vec![OpenLoopNode::new(None, None, 1.0, None)],
+        }
+    }
+
+    /// Moves the root of the tree to the child reached by `action`, keeping its subtree, see
+    /// `VecTree::advance`. `state` is the actual state after the action was played.
+    pub fn advance(&mut self, action: &A, state: G) {
+        let new_root_idx = self.child_with_action(0, action, &self.root_state.current_player());
+        self.root_state = state;
+
+        let Some(new_root_idx) = new_root_idx else {
+            self.nodes = vec![OpenLoopNode::new(None, None, 1.0, None)];
+            return;
+        };
+
+        reroot(&mut self.nodes, new_root_idx);
+
+        let root = &mut self.nodes[0];
+        root.action = None;
+        root.mover = None;
+    }
+
+    pub fn search_n<R: Rng>(&mut self, rng: &mut R, iterations: usize) -> Result<(), SearchError<G::Error>> {
+        for _ in 0..iterations {
+            self.search(rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Searches until the budget runs out, returning the number of iterations run.
+    pub fn search_budget<R: Rng>(&mut self, rng: &mut R, budget: &SearchBudget) -> Result<usize, SearchError<G::Error>> {
+        budget.run(self, Self::num_nodes, |tree| tree.search(rng))
+    }
+
+    /// Picks one of the root's children which the player to move in the root state may choose
+    /// with the final move policy.
+    pub fn best_action(&self) -> Option<A> {
+        let legal_actions = self.root_state.actions();
+        let mover = self.root_state.current_player();
+
+        let children: Vec<(A, f64, f64)> = self.nodes[0].children.iter()
+            .map(|child_idx| &self.nodes[*child_idx])
+            .filter(|node| node.mover.as_ref() == Some(&mover))
+            .filter_map(|node| node.action.clone().map(|action| (action, node.num_visits, node.mover_value())))
+            .filter(|(action, _, _)| legal_actions.contains(action))
+            .collect();
+
+        let summaries: Vec<(f64, f64)> = children.iter().map(|(_, visits, value)| (*visits, *value)).collect();
+
+        self.config.final_move_policy.choose(&summaries).map(|idx| children[idx].0.clone())
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn search<R: Rng>(&mut self, rng: &mut R) -> Result<(), SearchError<G::Error>> {
+        let mut state = self.root_state.clone();
+        let mut current_node_idx = 0;
+        let mut visited_nodes = vec![0];
+
+        // replay the tree's actions from the root, stopping at the first new child
+        while state.outcome().is_none() {
+            let legal_actions = state.actions();
+            if legal_actions.is_empty() {
+                return Err(SearchError::NoActions);
+            }
+
+            let mover = state.current_player();
+
+            let untried_actions: Vec<A> = legal_actions.iter()
+                .filter(|action| self.child_with_action(current_node_idx, action, &mover).is_none())
+                .cloned()
+                .collect();
+
+            // the most likely untried action, if the expansion policy lets one be added
+            let mut next_untried_action = None;
+
+            if !untried_actions.is_empty() {
+                let node = &self.nodes[current_node_idx];
+                let num_legal_children = legal_actions.len() - untried_actions.len();
+                let priors = self.config.priors(&state, &untried_actions);
+
+                match self.config.expansion {
+                    ExpansionPolicy::Eager => {
+                        for (action, prior) in untried_actions.into_iter().zip(priors) {
+                            self.add_node(action, mover.clone(), prior, current_node_idx);
+                        }
+                    }
+                    expansion if num_legal_children == 0 || expansion.allows_child(num_legal_children, node.num_visits) => {
+                        // ties go to the earliest action
+                        next_untried_action = untried_actions.into_iter().zip(priors)
+                            .fold(None, |acc: Option<(A, f64)>, (action, prior)| match acc {
+                                Some(acc) if acc.1 >= prior => Some(acc),
+                                _ => Some((action, prior)),
+                            });
+                    }
+                    _ => {}
+                }
+            }
+
+            let parent_visits = self.nodes[current_node_idx].num_visits;
+            let selected = self.select(current_node_idx, &legal_actions, &mover, rng);
+
+            if let Some((action, prior)) = next_untried_action {
+                let best_child_value = selected.as_ref().ok().map(|(_, value)| *value);
+
+                if self.config.prefers_untried_action(prior, parent_visits, best_child_value) {
+                    current_node_idx = self.add_node(action.clone(), mover, prior, current_node_idx);
+                    visited_nodes.push(current_node_idx);
+                    state = state.apply_action(action, rng).map_err(SearchError::IllegalAction)?;
+                    break;
+                }
+            }
+
+            (current_node_idx, _) = selected?;
+            visited_nodes.push(current_node_idx);
+
+            let action = self.nodes[current_node_idx].action.clone().expect("a child has an action");
+            state = state.apply_action(action, rng).map_err(SearchError::IllegalAction)?;
+
+            if self.nodes[current_node_idx].num_visits == 0.0 {
+                break;
+            }
+        }
+
+        let rewards = self.config.leaf_rewards(&state, &self.root_state.players(), rng, None)?;
+
+        for visited_node_idx in visited_nodes {
+            let node = &mut self.nodes[visited_node_idx];
+            node.num_visits += 1.0;
+            for (player, reward) in &rewards {
+                *node.value.entry(player.clone()).or_insert(0f64) += reward;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn child_with_action(&self, node_idx: usize, action: &A, mover: &P) -> Option<usize> {
+        self.nodes[node_idx].children.iter().copied().find(|child_idx| {
+            let child = &self.nodes[*child_idx];
+            child.action.as_ref() == Some(action) && child.mover.as_ref() == Some(mover)
+        })
+    }
+
+    /// Selects one of the node's children which `mover` may choose, counting it as available to all
+    /// of them, and returns it with its selection value.
+    fn select<R: Rng>(&mut self, node_idx: usize, legal_actions: &[A], mover: &P, rng: &mut R) -> Result<(usize, f64), SearchError<G::Error>> {
+        let compatible: Vec<usize> = self.nodes[node_idx].children.iter()
+            .copied()
+            .filter(|child_idx| {
+                let child = &self.nodes[*child_idx];
+                child.mover.as_ref() == Some(mover) && child.action.as_ref().is_some_and(|action| legal_actions.contains(action))
+            })
+            .collect();
+
+        for child_idx in &compatible {
+            self.nodes[*child_idx].availability += 1.0;
+        }
+
+        let selected = compatible.iter().fold((None, f64::MIN), |acc, child_idx| {
+            let value = self.selection_value(&self.nodes[*child_idx], rng);
+            if value > acc.1 {
+                (Some(*child_idx), value)
+            } else {
+                acc
+            }
+        });
+
+        selected.0.map(|child_idx| (child_idx, selected.1)).ok_or(SearchError::NoActions)
+    }
+
+    fn selection_value<R: Rng>(&self, node: &OpenLoopNode<P, A>, rng: &mut R) -> f64 {
+        let noise = rng.gen::<f64>() * self.config.noise;
+        let constant_of_exploration = self.config.exploration_constant;
+
+        match self.config.selection {
+            SelectionPolicy::Ucb1 => {
+                if node.num_visits == 0.0 {
+                    return f64::MAX;
+                }
+
+                let exploitation_component = node.mover_value() / node.num_visits;
+                let exploration_component = constant_of_exploration * (node.availability.ln() / node.num_visits).sqrt();
+
+                exploitation_component + exploration_component + noise
+            }
+            SelectionPolicy::Puct => {
+                let exploitation_component = if node.num_visits == 0.0 {
+                    0f64
+                } else {
+                    node.mover_value() / node.num_visits
+                };
+                let exploration_component = constant_of_exploration * node.prior * node.availability.sqrt() / (1.0 + node.num_visits);
+
+                exploitation_component + exploration_component + noise
+            }
+        }
+    }
+
+    fn add_node(&mut self, action: A, mover: P, prior: f64, parent_idx: usize) -> usize {
+        let next_idx = self.nodes.len();
+
+        self.nodes.push(OpenLoopNode::new(Some(action), Some(mover), prior, Some(parent_idx)));
+        self.nodes[parent_idx].children.push(next_idx);
+
+        next_idx
+    }
+}
+
+struct OpenLoopNode<P, A> {
+    action: Option<A>,
+    mover: Option<P>,
+    /// the prior probability of this node's action, given when it was added
+    prior: f64,
+    num_visits: f64,
+    /// the number of times this node's action was legal when its parent was selected from
+    availability: f64,
+    value: HashMap<P, f64>,
+    parent_idx: Option<usize>,
+    children: Vec<usize>,
+}
+
+impl<P: Eq + Hash, A> OpenLoopNode<P, A> {
+    fn new(action: Option<A>, mover: Option<P>, prior: f64, parent_idx: Option<usize>) -> Self {
+        OpenLoopNode {
+            action,
+            mover,
+            prior,
+            num_visits: 0.0,
+            availability: 0.0,
+            value: HashMap::new(),
+            parent_idx,
+            children: Vec::new(),
+        }
+    }
+
+    /// The total value of this node for the player who chose the action leading to it.
+    fn mover_value(&self) -> f64 {
+        self.mover.as_ref().and_then(|mover| self.value.get(mover)).copied().unwrap_or(0f64)
+    }
+}
+
+impl<P, A> ArenaNode for OpenLoopNode<P, A> {
+    fn parent_idx_mut(&mut self) -> &mut Option<usize> {
+        &mut self.parent_idx
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<usize> {
+        &mut self.children
+    }
+}
+
+/// Like `mcts_with_budget`, but with an `OpenLoopTree`.
+pub fn mcts_open_loop<
+    R: Rng + RngCore + Sized,
+    P: Eq + PartialEq + Hash + Clone,
+    A: Clone + PartialEq,
+    G: Mcts<P, A>
+>(game: &G, rng: &mut R, budget: &SearchBudget, config: MctsConfig<P, A, G>) -> Result<A, SearchError<G::Error>> {
+    let mut tree = OpenLoopTree::from_state(game.clone(), config);
+
+    tree.search_budget(rng, budget)?;
+
+    best_or_first_action(game, tree.best_action())
+}
@@ -0,0 +1,11 @@
+use std::sync::Arc;
+
+/// Prior probabilities of a state's actions, e.g. from hand-written move ordering or a learned policy.
+pub trait Prior<A> {
+    /// One non-negative weight per action, in the same order as `actions`. The weights don't need
+    /// to sum to 1, they are normalized before use.
+    fn priors(&self, actions: &[A]) -> Vec<f64>;
+}
+
+/// Weighs a state's actions, usually `Prior::priors`, but closures may capture e.g. a loaded policy.
+pub type PriorFn<A, G> = Arc<dyn Fn(&G, &[A]) -> Vec<f64> + Send + Sync>;
@@ -1,28 +1,14 @@
 use rand::Rng;
 use crate::mcts::Mcts;
-use crate::mcts::Outcome;
+use crate::mcts::{Outcome, RolloutPolicy, SearchError, UniformRollout};
 
+/// Plays random actions until the game ends. `max_steps` bounds the number of actions played,
+/// exceeding it is an error.
 pub fn random_rollout<
     R: Rng + Sized,
     P,
-    A: Clone,
-    G: Mcts<P, A> + Clone
->(game: &G, rng: &mut R) -> Outcome<P> {
-    let mut game = game.clone();
-
-    loop {
-        if let Some(outcome) = game.outcome() {
-            return outcome;
-        }
-
-        let actions = &game.actions()[..];
-        let random_action = rand::seq::SliceRandom::choose(actions, rng);
-
-        if let Some(action) = random_action {
-            game = game.apply_action((*action).clone(), rng).unwrap();
-        } else {
-            return Outcome::Escape("No actions available.".to_string());
-        }
-
-    }
+    A,
+    G: Mcts<P, A>
+>(game: &G, rng: &mut R, max_steps: Option<usize>) -> Result<Outcome<P>, SearchError<G::Error>> {
+    UniformRollout.rollout(game, rng, max_steps)
 }
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rand::RngCore;
+use crate::mcts::{Mcts, Outcome, Playout, RolloutPolicy, SearchError};
+
+/// What a rollout cut off by a `RolloutGuard` ends with.
+pub enum Cutoff<P, G> {
+    /// a draw, every player gets a reward of 0.5
+    Draw,
+    /// an `Outcome::Escape`, left to the search's `EscapePolicy`
+    Escape,
+    /// the evaluator's estimate of the state the rollout got to, as with `DepthLimited`
+    Evaluate(CutoffFn<P, G>),
+}
+
+/// Estimates the outcome of a state a rollout was cut off in.
+pub type CutoffFn<P, G> = Arc<dyn Fn(&G) -> Outcome<P> + Send + Sync>;
+
+impl<P, G> Clone for Cutoff<P, G> {
+    fn clone(&self) -> Self {
+        match self {
+            Cutoff::Draw => Cutoff::Draw,
+            Cutoff::Escape => Cutoff::Escape,
+            Cutoff::Evaluate(evaluator) => Cutoff::Evaluate(evaluator.clone()),
+        }
+    }
+}
+
+impl<P, G> Cutoff<P, G> {
+    fn outcome(&self, state: &G, players: Vec<P>, reason: &str) -> Outcome<P> {
+        match self {
+            Cutoff::Draw => Outcome::Ranking(vec![players]),
+            Cutoff::Escape => Outcome::Escape(reason.to_string()),
+            Cutoff::Evaluate(evaluator) => evaluator(state),
+        }
+    }
+}
+
+/// How often a `RolloutGuard` cut rollouts off.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RolloutGuardStats {
+    pub num_rollouts: usize,
+    /// rollouts cut off for reaching the depth limit
+    pub num_depth_cutoffs: usize,
+    /// rollouts cut off for coming back to a state they had already been in
+    pub num_repeat_cutoffs: usize,
+}
+
+/// Wraps another policy so that rollouts always end, for games which can go on forever, such as
+/// players passing back and forth. A rollout which plays `max_depth` actions, or with
+/// `with_repeat_detection` comes back to a state it was already in, is cut off with the `cutoff`
+/// outcome instead of playing on.
+///
+/// Unlike `DepthLimited`, the guard counts the rollouts it cut off, over all the searches it was used
+/// in. To read the counts after a search, keep the `Arc` the guard was put in `MctsConfig::rollout`
+/// with, e.g. `let guard = Arc::new(RolloutGuard::new(..)); config.rollout = guard.clone();`, and
+/// call `guard.stats()` on it, or pass `&guard` as the rollout policy of `ismcts_mt_with_budget`.
+pub struct RolloutGuard<R, P, G> {
+    pub policy: R,
+    pub max_depth: usize,
+    pub cutoff: Cutoff<P, G>,
+    /// hashes states to tell when one repeats, set by `with_repeat_detection`
+    state_hash: Option<fn(&G) -> u64>,
+    num_rollouts: AtomicUsize,
+    num_depth_cutoffs: AtomicUsize,
+    num_repeat_cutoffs: AtomicUsize,
+}
+
+impl<R, P, G> RolloutGuard<R, P, G> {
+    pub fn new(policy: R, max_depth: usize, cutoff: Cutoff<P, G>) -> Self {
+        RolloutGuard {
+            policy,
+            max_depth,
+            cutoff,
+            state_hash: None,
+            num_rollouts: AtomicUsize::new(0),
+            num_depth_cutoffs: AtomicUsize::new(0),
+            num_repeat_cutoffs: AtomicUsize::new(0),
+        }
+    }
+
+    /// Also cuts rollouts off when they come back to a state they were already in. States are told
+    /// apart by their hashes, so a hash collision can end a rollout early.
+    pub fn with_repeat_detection(mut self) -> Self where G: Hash {
+        self.state_hash = Some(hash_state::<G>);
+        self
+    }
+
+    pub fn stats(&self) -> RolloutGuardStats {
+        RolloutGuardStats {
+            num_rollouts: self.num_rollouts.load(Ordering::Relaxed),
+            num_depth_cutoffs: self.num_depth_cutoffs.load(Ordering::Relaxed),
+            num_repeat_cutoffs: self.num_repeat_cutoffs.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.num_rollouts.store(0, Ordering::Relaxed);
+        self.num_depth_cutoffs.store(0, Ordering::Relaxed);
+        self.num_repeat_cutoffs.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<P, A, G: Mcts<P, A>, R: RolloutPolicy<P, A, G>> RolloutPolicy<P, A, G> for RolloutGuard<R, P, G> {
+    fn choose(&self, state: &G, actions: Vec<A>, rng: &mut dyn RngCore) -> A {
+        self.policy.choose(state, actions, rng)
+    }
+
+    fn play_out(&self, state: &G, rng: &mut dyn RngCore, max_steps: Option<usize>, playout: Playout<'_, P, A, G>) -> Result<Outcome<P>, SearchError<G::Error>> {
+        self.num_rollouts.fetch_add(1, Ordering::Relaxed);
+
+        let mut seen_states: HashSet<u64> = HashSet::new();
+        let outer_cut_off = playout.cut_off;
+
+        let mut cut_off = |game: &G, steps: usize| {
+            if let Some(outcome) = outer_cut_off(game, steps) {
+                return Some(outcome);
+            }
+
+            if steps >= self.max_depth {
+                self.num_depth_cutoffs.fetch_add(1, Ordering::Relaxed);
+                return Some(self.cutoff.outcome(game, game.players(), "Rollout reached its depth limit."));
+            }
+
+            let state_hash = self.state_hash?;
+            if !seen_states.insert(state_hash(game)) {
+                self.num_repeat_cutoffs.fetch_add(1, Ordering::Relaxed);
+                return Some(self.cutoff.outcome(game, game.players(), "Rollout repeated a state."));
+            }
+
+            None
+        };
+
+        self.policy.play_out(state, rng, max_steps, Playout {
+            choose: playout.choose,
+            cut_off: &mut cut_off,
+            trace: playout.trace,
+        })
+    }
+}
+
+fn hash_state<G: Hash>(state: &G) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
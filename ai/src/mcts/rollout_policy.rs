@@ -0,0 +1,396 @@
+use rand::{Rng, RngCore};
+use crate::mcts::{Mcts, Outcome, SearchError};
+
+/// Decides how a game is played out from a freshly expanded state.
+///
+/// Policies only have to pick one action at a time, `rollout` plays those picks out to the end of
+/// the game. Policies which wrap another one, such as `DepthLimited`, or which want to watch or end
+/// playouts, override `play_out` and hand it on to the policy they wrap with their own `Playout`
+/// hooks added, so that wrappers compose.
+pub trait RolloutPolicy<P, A, G: Mcts<P, A>>: Send + Sync {
+    /// Picks the next action of a playout from the state's actions, which are never empty.
+    fn choose(&self, state: &G, actions: Vec<A>, rng: &mut dyn RngCore) -> A;
+
+    /// Plays the game out until it ends. `max_steps` bounds the number of actions played,
+    /// exceeding it is an error.
+    fn rollout(&self, state: &G, rng: &mut dyn RngCore, max_steps: Option<usize>) -> Result<Outcome<P>, SearchError<G::Error>> {
+        self.traced_rollout(state, rng, max_steps, &mut |_, _| {})
+    }
+
+    /// Like `rollout`, but calls `trace` with the state and the action played for every step.
+    fn traced_rollout(&self, state: &G, rng: &mut dyn RngCore, max_steps: Option<usize>, trace: &mut dyn FnMut(&G, &A)) -> Result<Outcome<P>, SearchError<G::Error>> {
+        self.play_out(state, rng, max_steps, Playout {
+            choose: &|state, actions, rng| self.choose(state, actions, rng),
+            cut_off: &mut |_, _| None,
+            trace,
+        })
+    }
+
+    /// Plays the game out with the given hooks, which `rollout` and `traced_rollout` set up with this
+    /// policy's `choose`. The default plays the hooks' choices until the game ends or is cut off.
+    fn play_out(&self, state: &G, rng: &mut dyn RngCore, max_steps: Option<usize>, playout: Playout<'_, P, A, G>) -> Result<Outcome<P>, SearchError<G::Error>> {
+        run_playout(state, rng, max_steps, playout)
+    }
+}
+
+/// The hooks a playout runs with, which wrapping policies add to before handing the playout on to
+/// the policy they wrap.
+pub struct Playout<'a, P, A, G> {
+    /// picks every action, the `choose` of the outermost policy
+    pub choose: &'a ChooseFn<'a, A, G>,
+    /// called before every step with the state and the number of actions played so far, a `Some`
+    /// ends the playout there with that outcome
+    pub cut_off: &'a mut dyn FnMut(&G, usize) -> Option<Outcome<P>>,
+    /// called with the state and the action played for every step
+    pub trace: &'a mut dyn FnMut(&G, &A),
+}
+
+type ChooseFn<'a, A, G> = dyn Fn(&G, Vec<A>, &mut dyn RngCore) -> A + 'a;
+
+/// The playout loop every policy ends up in.
+pub(crate) fn run_playout<P, A, G: Mcts<P, A>>(state: &G, mut rng: &mut dyn RngCore, max_steps: Option<usize>, playout: Playout<'_, P, A, G>) -> Result<Outcome<P>, SearchError<G::Error>> {
+    let mut game = state.clone();
+    let mut steps = 0usize;
+
+    loop {
+        if let Some(outcome) = game.outcome() {
+            return Ok(outcome);
+        }
+
+        if let Some(outcome) = (playout.cut_off)(&game, steps) {
+            return Ok(outcome);
+        }
+
+        if let Some(max_steps) = max_steps {
+            if steps >= max_steps {
+                return Err(SearchError::RolloutLimitExceeded(max_steps));
+            }
+        }
+
+        let actions = game.actions();
+        if actions.is_empty() {
+            return Ok(Outcome::Escape("No actions available.".to_string()));
+        }
+
+        let action = (playout.choose)(&game, actions, rng);
+        (playout.trace)(&game, &action);
+        game = game.apply_action(action, &mut rng).map_err(SearchError::IllegalAction)?;
+        steps += 1;
+    }
+}
+
+/// Picks every action uniformly at random.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UniformRollout;
+
+impl<P, A, G: Mcts<P, A>> RolloutPolicy<P, A, G> for UniformRollout {
+    fn choose(&self, _state: &G, mut actions: Vec<A>, rng: &mut dyn RngCore) -> A {
+        actions.swap_remove(rng.gen_range(0..actions.len()))
+    }
+}
+
+/// Picks the action the heuristic scores highest, except for a random action with probability
+/// `epsilon`.
+#[derive(Clone, Copy, Debug)]
+pub struct EpsilonGreedy<F> {
+    pub epsilon: f64,
+    /// scores an action in the given state, higher is better for the player to move
+    pub heuristic: F,
+}
+
+impl<F> EpsilonGreedy<F> {
+    pub fn new(epsilon: f64, heuristic: F) -> Self {
+        EpsilonGreedy {
+            epsilon,
+            heuristic,
+        }
+    }
+}
+
+impl<P, A, G: Mcts<P, A>, F: Fn(&G, &A) -> f64 + Send + Sync> RolloutPolicy<P, A, G> for EpsilonGreedy<F> {
+    fn choose(&self, state: &G, mut actions: Vec<A>, rng: &mut dyn RngCore) -> A {
+        if rng.gen::<f64>() < self.epsilon {
+            return actions.swap_remove(rng.gen_range(0..actions.len()));
+        }
+
+        let scores: Vec<f64> = actions.iter().map(|action| (self.heuristic)(state, action)).collect();
+
+        actions.swap_remove(first_max(&scores))
+    }
+}
+
+/// Picks actions at random with probability proportional to `exp(weight / temperature)`.
+///
+/// A high temperature plays close to uniformly, a low one close to greedily, and a temperature of
+/// 0 or less plays greedily, ties going to the first action.
+#[derive(Clone, Copy, Debug)]
+pub struct Softmax<F> {
+    pub temperature: f64,
+    /// weighs an action in the given state, higher is better for the player to move
+    pub weights: F,
+}
+
+impl<F> Softmax<F> {
+    pub fn new(temperature: f64, weights: F) -> Self {
+        Softmax {
+            temperature,
+            weights,
+        }
+    }
+}
+
+impl<P, A, G: Mcts<P, A>, F: Fn(&G, &A) -> f64 + Send + Sync> RolloutPolicy<P, A, G> for Softmax<F> {
+    fn choose(&self, state: &G, mut actions: Vec<A>, rng: &mut dyn RngCore) -> A {
+        let weights: Vec<f64> = actions.iter().map(|action| (self.weights)(state, action)).collect();
+        let idx = sample_softmax(&weights, self.temperature, rng);
+
+        actions.swap_remove(idx)
+    }
+}
+
+/// Samples an index with probability proportional to `exp(weight / temperature)`, or picks the
+/// first of the largest weights for a temperature of 0 or less.
+pub(crate) fn sample_softmax(weights: &[f64], temperature: f64, rng: &mut dyn RngCore) -> usize {
+    if temperature <= 0f64 {
+        return first_max(weights);
+    }
+
+    // shift by the largest weight so the exponentials can't overflow
+    let max_weight = weights.iter().copied().fold(f64::MIN, f64::max);
+    let exps: Vec<f64> = weights.iter().map(|weight| ((weight - max_weight) / temperature).exp()).collect();
+
+    let total: f64 = exps.iter().sum();
+    if !total.is_finite() || total <= 0f64 {
+        return rng.gen_range(0..weights.len());
+    }
+
+    let mut threshold = rng.gen::<f64>() * total;
+    for (idx, exp) in exps.iter().enumerate() {
+        threshold -= exp;
+        if threshold <= 0f64 {
+            return idx;
+        }
+    }
+
+    exps.len() - 1
+}
+
+/// The index of the largest weight, ties going to the first.
+fn first_max(weights: &[f64]) -> usize {
+    weights.iter().enumerate().fold((0, f64::MIN), |acc, (idx, weight)| {
+        if *weight > acc.1 {
+            (idx, *weight)
+        } else {
+            acc
+        }
+    }).0
+}
+
+/// Plays at most `max_depth` actions with the inner policy, then scores the state it got to with
+/// a static evaluator instead of playing the game out.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthLimited<R, E> {
+    pub policy: R,
+    pub max_depth: usize,
+    /// estimates the outcome of an unfinished game, e.g. as `Outcome::Scores`
+    pub evaluator: E,
+}
+
+impl<R, E> DepthLimited<R, E> {
+    pub fn new(policy: R, max_depth: usize, evaluator: E) -> Self {
+        DepthLimited {
+            policy,
+            max_depth,
+            evaluator,
+        }
+    }
+}
+
+impl<
+    P,
+    A,
+    G: Mcts<P, A>,
+    R: RolloutPolicy<P, A, G>,
+    E: Fn(&G) -> Outcome<P> + Send + Sync
+> RolloutPolicy<P, A, G> for DepthLimited<R, E> {
+    fn choose(&self, state: &G, actions: Vec<A>, rng: &mut dyn RngCore) -> A {
+        self.policy.choose(state, actions, rng)
+    }
+
+    fn play_out(&self, state: &G, rng: &mut dyn RngCore, max_steps: Option<usize>, playout: Playout<'_, P, A, G>) -> Result<Outcome<P>, SearchError<G::Error>> {
+        let outer_cut_off = playout.cut_off;
+
+        let mut cut_off = |game: &G, steps: usize| {
+            outer_cut_off(game, steps).or_else(|| (steps >= self.max_depth).then(|| (self.evaluator)(game)))
+        };
+
+        self.policy.play_out(state, rng, max_steps, Playout {
+            choose: playout.choose,
+            cut_off: &mut cut_off,
+            trace: playout.trace,
+        })
+    }
+}
+
+/// Wraps another policy with decisive and anti-decisive moves: an action which wins the game for
+/// the player to move right away is always taken, and actions after which the next player has such
+/// a win are avoided, unless every action is like that. A win is an `Outcome::Winner` of the player.
+///
+/// Looking ahead costs one `apply_action` per action checked, the actions after a random action are
+/// sampled once. `max_checks` bounds how many a step may make, after which the checks made so far
+/// are used, and 0 turns the lookahead off for games where playouts are cheap enough as they are.
+#[derive(Clone, Copy, Debug)]
+pub struct DecisiveMoves<R> {
+    pub policy: R,
+    pub max_checks: usize,
+    /// whether actions which hand the next player a win are avoided, on top of taking wins
+    pub anti_decisive: bool,
+}
+
+impl<R> DecisiveMoves<R> {
+    pub fn new(policy: R, max_checks: usize) -> Self {
+        DecisiveMoves {
+            policy,
+            max_checks,
+            anti_decisive: true,
+        }
+    }
+}
+
+impl<P: PartialEq, A: Clone, G: Mcts<P, A>, R: RolloutPolicy<P, A, G>> RolloutPolicy<P, A, G> for DecisiveMoves<R> {
+    fn choose(&self, state: &G, mut actions: Vec<A>, mut rng: &mut dyn RngCore) -> A {
+        let mover = state.current_player();
+        let mut checks = 0usize;
+
+        // the states after each action checked, kept for the anti-decisive checks
+        let mut next_states: Vec<(usize, G)> = Vec::new();
+
+        for (idx, action) in actions.iter().enumerate() {
+            if checks >= self.max_checks {
+                break;
+            }
+            checks += 1;
+
+            let Ok(next_state) = state.apply_action(action.clone(), &mut rng) else {
+                continue;
+            };
+
+            if is_win_for(&next_state, &mover) {
+                return actions.swap_remove(idx);
+            }
+
+            next_states.push((idx, next_state));
+        }
+
+        if !self.anti_decisive {
+            return self.policy.choose(state, actions, rng);
+        }
+
+        let mut hands_over_win = vec![false; actions.len()];
+
+        'actions: for (idx, next_state) in next_states {
+            let next_player = next_state.current_player();
+            if next_state.outcome().is_some() || next_player == mover {
+                continue;
+            }
+
+            for reply in next_state.actions() {
+                if checks >= self.max_checks {
+                    break 'actions;
+                }
+                checks += 1;
+
+                let wins = next_state.apply_action(reply, &mut rng).is_ok_and(|after_reply| is_win_for(&after_reply, &next_player));
+                if wins {
+                    hands_over_win[idx] = true;
+                    break;
+                }
+            }
+        }
+
+        if hands_over_win.iter().all(|hands_over_win| *hands_over_win) {
+            return self.policy.choose(state, actions, rng);
+        }
+
+        let safe_actions: Vec<A> = actions.into_iter()
+            .zip(hands_over_win)
+            .filter_map(|(action, hands_over_win)| (!hands_over_win).then_some(action))
+            .collect();
+
+        self.policy.choose(state, safe_actions, rng)
+    }
+
+    fn play_out(&self, state: &G, rng: &mut dyn RngCore, max_steps: Option<usize>, playout: Playout<'_, P, A, G>) -> Result<Outcome<P>, SearchError<G::Error>> {
+        self.policy.play_out(state, rng, max_steps, playout)
+    }
+}
+
+fn is_win_for<P: PartialEq, A, G: Mcts<P, A>>(state: &G, player: &P) -> bool {
+    matches!(state.outcome(), Some(Outcome::Winner(winner)) if winner == *player)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use crate::mcts::test_games::Pile;
+    use super::*;
+
+    const NUM_CHOICES: usize = 10000;
+
+    /// How often the policy picks taking 1 and taking 2 stones from a pile of 5.
+    fn frequencies<R: RolloutPolicy<usize, u8, Pile>>(policy: &R) -> [f64; 2] {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let pile = Pile::new(5, 0);
+
+        let mut counts = [0usize; 2];
+        for _ in 0..NUM_CHOICES {
+            let action = policy.choose(&pile, pile.actions(), &mut rng);
+            counts[action as usize - 1] += 1;
+        }
+
+        counts.map(|count| count as f64 / NUM_CHOICES as f64)
+    }
+
+    fn prefer_2(_: &Pile, action: &u8) -> f64 {
+        if *action == 2 { 3f64.ln() } else { 0.0 }
+    }
+
+    fn indifferent(_: &Pile, _: &u8) -> f64 {
+        1.0
+    }
+
+    #[test]
+    fn epsilon_greedy_explores_epsilon_of_the_time() {
+        assert_eq!(frequencies(&EpsilonGreedy::new(0.0, prefer_2)), [0.0, 1.0]);
+        assert_eq!(frequencies(&EpsilonGreedy::new(0.0, indifferent)), [1.0, 0.0]);
+
+        // half the time greedily, and half of the random half too
+        let [_, take_2] = frequencies(&EpsilonGreedy::new(0.5, prefer_2));
+        assert!((take_2 - 0.75).abs() < 0.02);
+
+        let [take_1, _] = frequencies(&EpsilonGreedy::new(1.0, prefer_2));
+        assert!((take_1 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn softmax_weighs_by_temperature() {
+        // exp(ln 3) against exp(0)
+        let [_, take_2] = frequencies(&Softmax::new(1.0, prefer_2));
+        assert!((take_2 - 0.75).abs() < 0.02);
+
+        // exp(ln 3 / 2) = sqrt 3 against exp(0)
+        let [_, take_2] = frequencies(&Softmax::new(2.0, prefer_2));
+        assert!((take_2 - 3f64.sqrt() / (3f64.sqrt() + 1.0)).abs() < 0.02);
+
+        let [take_1, _] = frequencies(&Softmax::new(1.0, indifferent));
+        assert!((take_1 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn softmax_without_temperature_is_greedy() {
+        for temperature in [0.0, -1.0] {
+            assert_eq!(frequencies(&Softmax::new(temperature, prefer_2)), [0.0, 1.0]);
+            assert_eq!(frequencies(&Softmax::new(temperature, indifferent)), [1.0, 0.0]);
+        }
+    }
+}
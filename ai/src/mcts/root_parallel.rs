@@ -0,0 +1,114 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+use rand::{Rng, RngCore, SeedableRng};
+use crate::budget::SearchBudget;
+use crate::mcts::{Mcts, MctsConfig, SearchError};
+use crate::mcts::mcts::{best_or_first_action, VecTree};
+use crate::parallel::run_bounded;
+use crate::rng_streams::RngStreams;
+
+/// Root parallel MCTS.
+///
+/// Several independent `VecTree`s over the same state are searched at the same time, each with its
+/// own `RngStreams` stream, and their root children are merged by action before picking the move. The
+/// trees are kept between searches, so they can be advanced through a game like a single tree.
+pub struct RootParallelTrees<P, A, G: Mcts<P, A>> {
+    trees: Vec<VecTree<P, A, G>>,
+    max_cores: usize,
+}
+
+impl<
+    P: Eq + PartialEq + Hash + Clone + Send,
+    A: Clone + PartialEq + Send,
+    G: Mcts<P, A> + Send
+> RootParallelTrees<P, A, G> where G::Error: Send {
+    /// Creates `num_trees` trees, which are searched on at most `max_cores` threads.
+    /// A `max_cores` of 0 uses every available core.
+    pub fn from_state(state: G, config: MctsConfig<P, A, G>, num_trees: usize, max_cores: usize) -> Self {
+        let trees = (0..num_trees.max(1)).map(|_| VecTree::from_state(state.clone(), config.clone())).collect();
+
+        RootParallelTrees {
+            trees,
+            max_cores,
+        }
+    }
+
+    pub fn search_n<R: Rng + RngCore + SeedableRng + Send>(&mut self, rng: &mut R, iterations: usize) -> Result<(), SearchError<G::Error>> {
+        self.search_budget(rng, &SearchBudget::iterations(iterations)).map(|_| ())
+    }
+
+    /// Searches every tree until the budget runs out, returning the total number of iterations run.
+    pub fn search_budget<R: Rng + RngCore + SeedableRng + Send>(&mut self, rng: &mut R, budget: &SearchBudget) -> Result<usize, SearchError<G::Error>> {
+        // a single tree can just use the caller's rng directly
+        if let [tree] = &mut self.trees[..] {
+            return tree.search_budget(rng, budget);
+        }
+
+        // splitting moves the caller's rng along, so the next search doesn't repeat this one
+        let streams = RngStreams::from_rng(rng);
+
+        let jobs: Vec<(&mut VecTree<P, A, G>, R)> = self.trees.iter_mut()
+            .enumerate()
+            .map(|(tree_idx, tree)| (tree, streams.stream(tree_idx as u64)))
+            .collect();
+
+        let results = run_bounded(jobs, self.max_cores, |(tree, mut rng)| tree.search_budget(&mut rng, budget));
+
+        results.into_iter().sum()
+    }
+
+    /// Re-roots every tree at the played action, see `VecTree::advance`.
+    pub fn advance(&mut self, action: &A, state: G) {
+        for tree in self.trees.iter_mut() {
+            tree.advance(action, state.clone());
+        }
+    }
+
+    pub fn trees(&self) -> &[VecTree<P, A, G>] {
+        &self.trees
+    }
+
+    /// The root children of all trees merged by action, as the action, its total visits, and the
+    /// total reward of the player who chose it, summed over the trees.
+    pub fn root_children(&self) -> Vec<(A, f64, f64)> {
+        let mut merged: Vec<(A, f64, f64)> = Vec::new();
+
+        for tree in &self.trees {
+            for (action, visits, value) in tree.root_children() {
+                match merged.iter_mut().find(|(merged_action, _, _)| *merged_action == action) {
+                    Some(child) => {
+                        child.1 += visits;
+                        child.2 += value;
+                    }
+                    None => merged.push((action, visits, value)),
+                }
+            }
+        }
+
+        merged
+    }
+
+    /// Merges the root children of all trees by action, then picks one with the final move policy.
+    pub fn best_action(&self) -> Option<A> {
+        let merged = self.root_children();
+        let summaries: Vec<(f64, f64)> = merged.iter().map(|(_, visits, value)| (*visits, *value)).collect();
+
+        self.trees[0].config().final_move_policy.choose(&summaries).map(|idx| merged[idx].0.clone())
+    }
+}
+
+/// Like `mcts_with_budget`, but searches `num_trees` trees on at most `max_cores` threads.
+pub fn mcts_root_parallel<
+    R: Rng + RngCore + SeedableRng + Send,
+    P: Eq + PartialEq + Hash + Clone + Send,
+    A: Eq + PartialEq + Hash + Clone + Debug + Send,
+    G: Mcts<P, A> + Send
+>(game: &G, rng: &mut R, budget: &SearchBudget, config: MctsConfig<P, A, G>, num_trees: usize, max_cores: usize) -> Result<A, SearchError<G::Error>>
+    where G::Error: Send
+{
+    let mut trees = RootParallelTrees::from_state(game.clone(), config, num_trees, max_cores);
+
+    trees.search_budget(rng, budget)?;
+
+    best_or_first_action(game, trees.best_action())
+}
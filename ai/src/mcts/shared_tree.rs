@@ -0,0 +1,371 @@
+use std::hash::Hash;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use rand::{Rng, RngCore, SeedableRng};
+use crate::budget::SearchBudget;
+use crate::mcts::mcts::best_or_first_action;
+use crate::mcts::{Mcts, MctsConfig, SearchError, SelectionPolicy};
+use crate::parallel::num_workers;
+use crate::rng_streams::RngStreams;
+
+/// Tree parallel MCTS.
+///
+/// A single tree which is searched by several threads at once. Statistics are kept in atomics, so
+/// threads only need exclusive access to the tree to add nodes. While a thread is on its way down to
+/// a leaf and back, every node on its path carries a virtual loss, which makes the other threads
+/// less likely to follow the same path.
+///
+/// Nodes are always expanded all at once, as with `ExpansionPolicy::Eager`, so that a single thread
+/// can expand a node while the others keep searching. Actions with random results keep the one
+/// result `apply_action` sampled, `Mcts::chance_outcomes` is only used by `VecTree`.
+pub struct SharedTree<P, A, G: Mcts<P, A>> {
+    config: MctsConfig<P, A, G>,
+    players: Vec<P>,
+    virtual_loss: f64,
+    nodes: RwLock<Vec<SharedTreeNode<A, G>>>,
+}
+
+impl<
+    P: Eq + PartialEq + Hash + Clone + Send + Sync,
+    A: Clone + PartialEq + Send + Sync,
+    G: Mcts<P, A> + Send + Sync
+> SharedTree<P, A, G> where G::Error: Send {
+    pub fn from_state(state: G, config: MctsConfig<P, A, G>) -> Self {
+        let players = state.players();
+
+        SharedTree {
+            config,
+            nodes: RwLock::new(vec![SharedTreeNode::new(state, None, None, players.len())]),
+            players,
+            virtual_loss: 1.0,
+        }
+    }
+
+    /// Sets how many visits each in-flight thread adds to the nodes on its path, 1 by default.
+    pub fn with_virtual_loss(mut self, virtual_loss: f64) -> Self {
+        self.virtual_loss = virtual_loss;
+        self
+    }
+
+    pub fn search_n<R: Rng + RngCore + SeedableRng + Send>(&mut self, rng: &mut R, iterations: usize, max_cores: usize) -> Result<(), SearchError<G::Error>> {
+        self.search_budget(rng, &SearchBudget::iterations(iterations), max_cores).map(|_| ())
+    }
+
+    /// Searches the tree on `max_cores` threads until the budget runs out, returning the total
+    /// number of iterations run. A `max_cores` of 0 uses every available core.
+    pub fn search_budget<R: Rng + RngCore + SeedableRng + Send>(&mut self, rng: &mut R, budget: &SearchBudget, max_cores: usize) -> Result<usize, SearchError<G::Error>> {
+        let tracker = budget.start();
+        let iterations = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let error: Mutex<Option<SearchError<G::Error>>> = Mutex::new(None);
+
+        // splitting moves the caller's rng along, so the next search doesn't repeat this one
+        let streams = RngStreams::from_rng(rng);
+        let rngs: Vec<R> = (0..num_workers(max_cores)).map(|worker_idx| streams.stream(worker_idx as u64)).collect();
+
+        let tree = &*self;
+
+        thread::scope(|scope| {
+            for mut rng in rngs {
+                let (tracker, iterations, failed, error) = (&tracker, &iterations, &failed, &error);
+
+                scope.spawn(move || {
+                    loop {
+                        if failed.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        // claim an iteration before running it, so the threads don't overshoot
+                        let claimed = iterations.fetch_add(1, Ordering::Relaxed);
+                        if tracker.is_exhausted(claimed, tree.num_nodes()) {
+                            iterations.fetch_sub(1, Ordering::Relaxed);
+                            break;
+                        }
+
+                        if let Err(search_error) = tree.search(&mut rng) {
+                            failed.store(true, Ordering::Relaxed);
+                            error.lock().unwrap().get_or_insert(search_error);
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        match error.into_inner().unwrap() {
+            Some(error) => Err(error),
+            None => Ok(iterations.into_inner()),
+        }
+    }
+
+    pub fn best_action(&self) -> Option<A> {
+        let nodes = self.nodes.read().unwrap();
+
+        let children: Vec<(A, f64, f64)> = nodes[0].children.iter().map(|child_idx| {
+            let node = &nodes[*child_idx];
+            (node.action.clone().expect("a child has an action"), node.num_visits.load(), node.mover_value())
+        }).collect();
+
+        let summaries: Vec<(f64, f64)> = children.iter().map(|(_, visits, value)| (*visits, *value)).collect();
+
+        self.config.final_move_policy.choose(&summaries).map(|idx| children[idx].0.clone())
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.read().unwrap().len()
+    }
+
+    fn search<R: Rng>(&self, rng: &mut R) -> Result<(), SearchError<G::Error>> {
+        let mut visited_nodes = vec![0];
+        self.nodes.read().unwrap()[0].virtual_loss.add(self.virtual_loss);
+
+        let rewards = self.descend(&mut visited_nodes, rng)
+            .and_then(|playout_state| self.config.leaf_rewards(&playout_state, &self.players, rng, None));
+
+        let nodes = self.nodes.read().unwrap();
+        for visited_node_idx in visited_nodes {
+            let node = &nodes[visited_node_idx];
+
+            // a failed iteration only takes back its virtual loss
+            if let Ok(rewards) = &rewards {
+                for (player_idx, player) in self.players.iter().enumerate() {
+                    node.value[player_idx].add(rewards.get(player).copied().unwrap_or(0f64));
+                }
+                node.num_visits.add(1.0);
+            }
+            node.virtual_loss.add(-self.virtual_loss);
+        }
+
+        rewards.map(|_| ())
+    }
+
+    /// Selects a path down to a leaf, expanding it if no other thread is, and returns the state to
+    /// play out from. Every node the path goes through is added to `visited_nodes`.
+    fn descend<R: Rng>(&self, visited_nodes: &mut Vec<usize>, rng: &mut R) -> Result<G, SearchError<G::Error>> {
+        // recursively select an optimal node to expand
+        let mut current_node_idx = 0;
+        loop {
+            let nodes = self.nodes.read().unwrap();
+            if nodes[current_node_idx].children.is_empty() {
+                break;
+            }
+
+            current_node_idx = self.select(&nodes, current_node_idx, rng)?;
+            visited_nodes.push(current_node_idx);
+        }
+
+        let node_state = self.nodes.read().unwrap()[current_node_idx].state.clone();
+
+        if node_state.outcome().is_some() {
+            return Ok(node_state);
+        }
+
+        if self.nodes.read().unwrap()[current_node_idx].expanding.swap(true, Ordering::AcqRel) {
+            // only one thread gets to expand a node, the others play out from it as it is
+            return Ok(node_state);
+        }
+
+        if let Err(error) = self.expand(current_node_idx, node_state, rng) {
+            // let the node be expanded again
+            self.nodes.read().unwrap()[current_node_idx].expanding.store(false, Ordering::Release);
+            return Err(error);
+        }
+
+        let nodes = self.nodes.read().unwrap();
+        let new_node_idx = self.select(&nodes, current_node_idx, rng)?;
+        visited_nodes.push(new_node_idx);
+
+        Ok(nodes[new_node_idx].state.clone())
+    }
+
+    fn expand<R: Rng>(&self, node_idx: usize, state: G, rng: &mut R) -> Result<(), SearchError<G::Error>> {
+        let actions = state.actions();
+
+        if actions.is_empty() {
+            return Err(SearchError::NoActions);
+        }
+
+        let mover_idx = self.players.iter().position(|player| *player == state.current_player());
+        let priors = self.config.priors(&state, &actions);
+
+        // apply the actions before taking the write lock, so the other threads can keep going
+        let children = actions.into_iter().zip(priors).map(|(action, prior)| {
+            let child_state = state.apply_action(action.clone(), rng).map_err(SearchError::IllegalAction)?;
+            let mut child = SharedTreeNode::new(child_state, Some(action), mover_idx, self.players.len());
+            child.prior = prior;
+            Ok(child)
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let mut nodes = self.nodes.write().unwrap();
+        let first_child_idx = nodes.len();
+        nodes.extend(children);
+        nodes[node_idx].children = (first_child_idx..nodes.len()).collect();
+
+        Ok(())
+    }
+
+    fn select<R: Rng>(&self, nodes: &[SharedTreeNode<A, G>], node_idx: usize, rng: &mut R) -> Result<usize, SearchError<G::Error>> {
+        let node = &nodes[node_idx];
+        let parent_visits = node.num_visits.load() + node.virtual_loss.load();
+
+        let selected = node.children.iter().fold((None, f64::MIN), |acc, child_idx| {
+            let ucb = match self.config.selection {
+                SelectionPolicy::Ucb1 => self.ucbt_value(&nodes[*child_idx], parent_visits, rng),
+                SelectionPolicy::Puct => self.puct_value(&nodes[*child_idx], parent_visits, rng),
+            };
+            if ucb > acc.1 {
+                (Some(*child_idx), ucb)
+            } else {
+                acc
+            }
+        });
+
+        let selected = selected.0.ok_or(SearchError::NoActions)?;
+        nodes[selected].virtual_loss.add(self.virtual_loss);
+
+        Ok(selected)
+    }
+
+    fn ucbt_value<R: Rng>(&self, node: &SharedTreeNode<A, G>, parent_visits: f64, rng: &mut R) -> f64 {
+        // in-flight visits count as losses, they add visits without adding value
+        let num_visits = node.num_visits.load() + node.virtual_loss.load();
+
+        if num_visits == 0.0 {
+            return f64::MAX;
+        }
+
+        let noise = rng.gen::<f64>() * self.config.noise;
+        let exploitation_component = node.mover_value() / num_visits;
+        let exploration_component = self.config.exploration_constant * ((parent_visits + 1.0).ln() / num_visits).sqrt();
+
+        exploitation_component + exploration_component + noise
+    }
+
+    fn puct_value<R: Rng>(&self, node: &SharedTreeNode<A, G>, parent_visits: f64, rng: &mut R) -> f64 {
+        let noise = rng.gen::<f64>() * self.config.noise;
+        let num_visits = node.num_visits.load() + node.virtual_loss.load();
+
+        let exploitation_component = if num_visits == 0.0 {
+            0f64
+        } else {
+            node.mover_value() / num_visits
+        };
+        let exploration_component = self.config.exploration_constant * node.prior * parent_visits.sqrt() / (1.0 + num_visits);
+
+        exploitation_component + exploration_component + noise
+    }
+}
+
+struct SharedTreeNode<A, G> {
+    state: G,
+    action: Option<A>,
+    /// index into the tree's players of the player who chose this node's action
+    mover_idx: Option<usize>,
+    /// the prior probability of this node's action, given when its parent was expanded
+    prior: f64,
+    children: Vec<usize>,
+    expanding: AtomicBool,
+    num_visits: AtomicF64,
+    virtual_loss: AtomicF64,
+    /// total value per player, indexed like the tree's players
+    value: Vec<AtomicF64>,
+}
+
+impl<A, G> SharedTreeNode<A, G> {
+    fn new(state: G, action: Option<A>, mover_idx: Option<usize>, num_players: usize) -> Self {
+        SharedTreeNode {
+            state,
+            action,
+            mover_idx,
+            prior: 1.0,
+            children: Vec::new(),
+            expanding: AtomicBool::new(false),
+            num_visits: AtomicF64::default(),
+            virtual_loss: AtomicF64::default(),
+            value: (0..num_players).map(|_| AtomicF64::default()).collect(),
+        }
+    }
+
+    fn mover_value(&self) -> f64 {
+        self.mover_idx.map(|mover_idx| self.value[mover_idx].load()).unwrap_or(0f64)
+    }
+}
+
+/// An f64 which can be added to from several threads, stored as its bits.
+#[derive(Default)]
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, delta: f64) {
+        let _ = self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + delta).to_bits())
+        });
+    }
+}
+
+/// Like `mcts_with_budget`, but searches a single shared tree on at most `max_cores` threads.
+pub fn mcts_tree_parallel<
+    R: Rng + RngCore + SeedableRng + Send,
+    P: Eq + PartialEq + Hash + Clone + Send + Sync,
+    A: Clone + PartialEq + Send + Sync,
+    G: Mcts<P, A> + Send + Sync
+>(game: &G, rng: &mut R, budget: &SearchBudget, config: MctsConfig<P, A, G>, max_cores: usize) -> Result<A, SearchError<G::Error>>
+    where G::Error: Send
+{
+    let mut tree = SharedTree::from_state(game.clone(), config);
+
+    tree.search_budget(rng, budget, max_cores)?;
+
+    best_or_first_action(game, tree.best_action())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use crate::mcts::test_games::Pile;
+    use super::*;
+
+    fn assert_no_virtual_loss(tree: &SharedTree<usize, u8, Pile>) {
+        let nodes = tree.nodes.read().unwrap();
+        assert!(nodes.iter().all(|node| node.virtual_loss.load() == 0.0));
+    }
+
+    #[test]
+    fn every_iteration_visits_one_root_child() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+        let mut tree = SharedTree::from_state(Pile::new(12, 0), MctsConfig::default());
+
+        // expand the root first, so that no thread plays out from the root itself
+        tree.search_n(&mut rng, 1, 1).unwrap();
+        tree.search_n(&mut rng, 400, 4).unwrap();
+
+        let nodes = tree.nodes.read().unwrap();
+        let child_visits: f64 = nodes[0].children.iter().map(|child_idx| nodes[*child_idx].num_visits.load()).sum();
+        assert_eq!(nodes[0].num_visits.load(), 401.0);
+        assert_eq!(child_visits, 401.0);
+        drop(nodes);
+
+        assert_no_virtual_loss(&tree);
+    }
+
+    #[test]
+    fn failed_iterations_take_back_their_virtual_loss() {
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+        // the root can't be expanded, since taking 2 stones breaks the floor
+        let mut tree = SharedTree::from_state(Pile { stones: 6, floor: 5, player: 0 }, MctsConfig::default());
+        assert!(matches!(tree.search_n(&mut rng, 100, 4), Err(SearchError::IllegalAction(()))));
+        assert_no_virtual_loss(&tree);
+        assert!(!tree.nodes.read().unwrap()[0].expanding.load(Ordering::Acquire));
+
+        // the root expands, but the rollouts break the floor
+        let mut tree = SharedTree::from_state(Pile { stones: 7, floor: 5, player: 0 }, MctsConfig::default());
+        assert!(matches!(tree.search_n(&mut rng, 100, 4), Err(SearchError::IllegalAction(()))));
+        assert_no_virtual_loss(&tree);
+    }
+}
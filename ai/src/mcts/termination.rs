@@ -1,6 +1,176 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use crate::mcts::SearchError;
+
 #[allow(dead_code)]
+#[derive(Clone, Debug)]
 pub enum Outcome<P> {
     Winner(P),
     Winners(Vec<P>),
-    Escape(String)
-}
\ No newline at end of file
+    Escape(String),
+    /// raw per-player scores, such as points at the end of the game
+    Scores(HashMap<P, f64>),
+    /// players grouped by finishing position, best first; players in the same group are tied
+    Ranking(Vec<Vec<P>>),
+}
+
+impl<P: Eq + Hash + Clone> Outcome<P> {
+    /// Normalizes the outcome into a reward in `[0, 1]` for each of `players`.
+    ///
+    /// Shared wins split a reward of 1 evenly between the winners. An escape is worth nothing to
+    /// anyone here; use an `EscapePolicy` to decide how escapes should be treated by a search.
+    /// Scores are min-max scaled across the players, and rankings are spread evenly from 1 for the
+    /// first group down to 0 for the last. A game where everyone scored or ranked the same counts as
+    /// a draw, worth 0.5 to each player. Players missing from a score or ranking are treated as having
+    /// scored 0 or placed last respectively.
+    pub fn rewards(&self, players: &[P]) -> HashMap<P, f64> {
+        match self {
+            Outcome::Winner(winner) => {
+                players.iter().map(|p| (p.clone(), if p == winner { 1.0 } else { 0.0 })).collect()
+            }
+            Outcome::Winners(winners) => {
+                let share = 1.0 / winners.len().max(1) as f64;
+                players.iter().map(|p| (p.clone(), if winners.contains(p) { share } else { 0.0 })).collect()
+            }
+            Outcome::Escape(_) => {
+                players.iter().map(|p| (p.clone(), 0.0)).collect()
+            }
+            Outcome::Scores(scores) => {
+                let raw: Vec<f64> = players.iter().map(|p| scores.get(p).copied().unwrap_or(0.0)).collect();
+                let min = raw.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = raw.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+                players.iter().zip(raw).map(|(p, score)| {
+                    let reward = if max > min { (score - min) / (max - min) } else { 0.5 };
+                    (p.clone(), reward)
+                }).collect()
+            }
+            Outcome::Ranking(groups) => {
+                let last = groups.len().saturating_sub(1);
+
+                players.iter().map(|p| {
+                    let reward = match groups.iter().position(|group| group.contains(p)) {
+                        Some(_) if last == 0 => 0.5,
+                        Some(position) => (last - position) as f64 / last as f64,
+                        None => 0.0,
+                    };
+                    (p.clone(), reward)
+                }).collect()
+            }
+        }
+    }
+}
+
+/// What a search does with a playout that ended in `Outcome::Escape`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EscapePolicy {
+    /// every player gets a reward of 0.5
+    #[default]
+    Draw,
+    /// every player gets a reward of 0
+    LossForAll,
+    /// play out again, up to the given number of times, then give up with an error
+    Retry(usize),
+    /// give up with an error
+    Error,
+}
+
+impl EscapePolicy {
+    /// Turns `outcome` into per-player rewards, applying the policy if it is an escape.
+    /// `rollout` is called to produce a fresh outcome when retrying.
+    pub fn resolve<P: Eq + Hash + Clone, E, F: FnMut() -> Result<Outcome<P>, SearchError<E>>>(
+        &self,
+        outcome: Outcome<P>,
+        players: &[P],
+        mut rollout: F,
+    ) -> Result<HashMap<P, f64>, SearchError<E>> {
+        let mut outcome = outcome;
+        let mut retries = 0;
+
+        loop {
+            let reason = match outcome {
+                Outcome::Escape(reason) => reason,
+                outcome => return Ok(outcome.rewards(players)),
+            };
+
+            match self {
+                EscapePolicy::Draw => return Ok(players.iter().map(|p| (p.clone(), 0.5)).collect()),
+                EscapePolicy::LossForAll => return Ok(players.iter().map(|p| (p.clone(), 0.0)).collect()),
+                EscapePolicy::Retry(max_retries) if retries < *max_retries => {
+                    retries += 1;
+                    outcome = rollout()?;
+                }
+                EscapePolicy::Retry(_) | EscapePolicy::Error => return Err(SearchError::Escaped(reason)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewards_are_normalized() {
+        let players = [0usize, 1, 2, 3];
+
+        // groups are spread evenly from 1 down to 0, tied players share a group, missing players are last
+        let rewards = Outcome::Ranking(vec![vec![2], vec![0, 1]]).rewards(&players);
+        assert_eq!(rewards[&2], 1.0);
+        assert_eq!(rewards[&0], 0.0);
+        assert_eq!(rewards[&1], 0.0);
+        assert_eq!(rewards[&3], 0.0);
+
+        let rewards = Outcome::Ranking(vec![vec![1], vec![0, 3], vec![2]]).rewards(&players);
+        assert_eq!(rewards[&1], 1.0);
+        assert_eq!(rewards[&0], 0.5);
+        assert_eq!(rewards[&3], 0.5);
+        assert_eq!(rewards[&2], 0.0);
+
+        // everyone in one group is a draw
+        let rewards = Outcome::Ranking(vec![players.to_vec()]).rewards(&players);
+        assert!(rewards.values().all(|reward| *reward == 0.5));
+
+        // scores are min-max scaled, missing players scored 0
+        let scores = HashMap::from([(0, 10.0), (1, 30.0), (2, 20.0)]);
+        let rewards = Outcome::Scores(scores).rewards(&players);
+        assert_eq!(rewards[&1], 1.0);
+        assert_eq!(rewards[&2], 2.0 / 3.0);
+        assert_eq!(rewards[&0], 1.0 / 3.0);
+        assert_eq!(rewards[&3], 0.0);
+
+        // tied scores are a draw, whatever the score
+        let scores = HashMap::from([(0, 7.0), (1, 7.0), (2, 7.0), (3, 7.0)]);
+        let rewards = Outcome::Scores(scores).rewards(&players);
+        assert!(rewards.values().all(|reward| *reward == 0.5));
+    }
+
+    #[test]
+    fn escape_policy_retries_escaped_playouts() {
+        let players = [0usize, 1];
+
+        // escapes twice more, then player 1 wins
+        let playouts = || {
+            let mut outcomes = vec![Outcome::Winner(1), Outcome::Escape("again".to_string()), Outcome::Escape("again".to_string())];
+            move || Ok::<_, SearchError<()>>(outcomes.pop().expect("no more retries than outcomes"))
+        };
+
+        let mut num_playouts = 0;
+        let mut playout = playouts();
+        let rewards = EscapePolicy::Retry(3).resolve(Outcome::Escape("first".to_string()), &players, || {
+            num_playouts += 1;
+            playout()
+        }).unwrap();
+        assert_eq!(num_playouts, 3);
+        assert_eq!(rewards[&1], 1.0);
+        assert_eq!(rewards[&0], 0.0);
+
+        // running out of retries gives up with the last escape
+        let result = EscapePolicy::Retry(2).resolve(Outcome::Escape("first".to_string()), &players, playouts());
+        assert!(matches!(result, Err(SearchError::Escaped(reason)) if reason == "again"));
+
+        // an outcome which didn't escape is never retried
+        let rewards = EscapePolicy::Retry(2).resolve(Outcome::Winner(0), &players, || -> Result<Outcome<usize>, SearchError<()>> { panic!("retried a finished playout") }).unwrap();
+        assert_eq!(rewards[&0], 1.0);
+    }
+}
@@ -0,0 +1,46 @@
+use rand::Rng;
+use crate::mcts::{Mcts, Outcome};
+
+/// Nim taking 1 or 2 stones, whoever takes the last stone wins. Taking more than leaves `floor`
+/// stones is an error, for tests of how searches handle game errors.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Pile {
+    pub(crate) stones: u8,
+    pub(crate) floor: u8,
+    pub(crate) player: usize,
+}
+
+impl Pile {
+    pub(crate) fn new(stones: u8, player: usize) -> Self {
+        Pile { stones, floor: 0, player }
+    }
+}
+
+impl Mcts<usize, u8> for Pile {
+    type Error = ();
+
+    fn actions(&self) -> Vec<u8> {
+        (1..=self.stones.min(2)).collect()
+    }
+
+    fn apply_action<R: Rng + Sized>(&self, action: u8, _rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+        if self.stones < self.floor + action {
+            return Err(());
+        }
+
+        Ok(Pile { stones: self.stones - action, floor: self.floor, player: 1 - self.player })
+    }
+
+    fn outcome(&self) -> Option<Outcome<usize>> {
+        // whoever took the last stone won, and it's no longer their turn
+        (self.stones == 0).then_some(Outcome::Winner(1 - self.player))
+    }
+
+    fn current_player(&self) -> usize {
+        self.player
+    }
+
+    fn players(&self) -> Vec<usize> {
+        vec![0, 1]
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use rand::{Rng, RngCore};
+use crate::budget::SearchBudget;
+use crate::mcts::config::order_untried_actions;
+use crate::mcts::mcts::best_or_first_action;
+use crate::mcts::{ExpansionPolicy, Mcts, MctsConfig, SearchError, SelectionPolicy};
+
+/// Which nodes a `TranspositionTree` drops when it is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReplacementPolicy {
+    /// the nodes which have gone the longest without being passed through
+    #[default]
+    LeastRecentlyUsed,
+    /// the nodes with the fewest visits
+    LeastVisited,
+}
+
+/// MCTS over a transposition table, for games where the same state can be reached by different
+/// sequences of actions.
+///
+/// Nodes are stored once per state in a `HashMap`, so transpositions share them, and statistics are
+/// kept on the edges between states (UCT on a DAG). Actions are applied again on every descent, so
+/// random results are sampled afresh and simply lead to whichever state they lead to. A descent
+/// which comes back to a state already on its path stops there.
+pub struct TranspositionTree<P, A, G: Mcts<P, A> + Hash + Eq> {
+    config: MctsConfig<P, A, G>,
+    root_state: G,
+    table: HashMap<G, TranspositionNode<P, A>>,
+    capacity: Option<usize>,
+    replacement: ReplacementPolicy,
+    /// the number of searches run so far, used to tell how recently nodes were used
+    clock: u64,
+}
+
+impl<P: Eq + PartialEq + Hash + Clone, A: Clone + PartialEq, G: Mcts<P, A> + Hash + Eq> TranspositionTree<P, A, G> {
+    pub fn from_state(state: G, config: MctsConfig<P, A, G>) -> Self {
+        TranspositionTree {
+            config,
+            root_state: state,
+            table: HashMap::new(),
+            capacity: None,
+            replacement: ReplacementPolicy::default(),
+            clock: 0,
+        }
+    }
+
+    /// Bounds the table to `capacity` nodes. Once it is full, the nodes the replacement policy
+    /// ranks lowest are dropped to make room, except for the root and the current search path.
+    pub fn with_capacity(mut self, capacity: usize, replacement: ReplacementPolicy) -> Self {
+        self.capacity = Some(capacity.max(1));
+        self.replacement = replacement;
+        self
+    }
+
+    /// Moves the root to `state`, the actual state after `action` was played. Every node in the
+    /// table is kept, as it may be reached again from the new root.
+    pub fn advance(&mut self, _action: &A, state: G) {
+        self.root_state = state;
+    }
+
+    pub fn search_n<R: Rng>(&mut self, rng: &mut R, iterations: usize) -> Result<(), SearchError<G::Error>> {
+        for _ in 0..iterations {
+            self.search(rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Searches until the budget runs out, returning the number of iterations run.
+    pub fn search_budget<R: Rng>(&mut self, rng: &mut R, budget: &SearchBudget) -> Result<usize, SearchError<G::Error>> {
+        budget.run(self, Self::num_nodes, |tree| tree.search(rng))
+    }
+
+    pub fn best_action(&self) -> Option<A> {
+        let root = self.table.get(&self.root_state)?;
+
+        let summaries: Vec<(f64, f64)> = root.edges.iter().map(|edge| (edge.num_visits, edge.value_for(&root.mover))).collect();
+
+        self.config.final_move_policy.choose(&summaries).map(|idx| root.edges[idx].action.clone())
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn search<R: Rng>(&mut self, rng: &mut R) -> Result<(), SearchError<G::Error>> {
+        self.clock += 1;
+
+        let mut state = self.root_state.clone();
+
+        // each state passed through, with the index of the edge taken out of it
+        let mut path: Vec<(G, usize)> = Vec::new();
+
+        while state.outcome().is_none() && !path.iter().any(|(path_state, _)| *path_state == state) {
+            let is_new = !self.table.contains_key(&state);
+            if is_new {
+                self.make_room(&path);
+                self.table.insert(state.clone(), TranspositionNode::new(state.current_player()));
+            }
+
+            if self.table[&state].untried_actions.is_none() {
+                self.expand(&state)?;
+            }
+
+            let node = self.table.get_mut(&state).expect("the node was just added");
+            node.last_used = self.clock;
+
+            let next_untried_prior = node.untried_actions.as_ref().and_then(|untried| untried.last()).map(|(_, prior)| *prior);
+            let may_add_edge = next_untried_prior.is_some() && (node.edges.is_empty() || self.config.expansion.allows_child(node.edges.len(), node.num_visits));
+            let parent_visits = node.num_visits;
+
+            let selected = self.select(&state, rng);
+
+            let tries_action = match (may_add_edge, next_untried_prior) {
+                (true, Some(prior)) => self.config.prefers_untried_action(prior, parent_visits, selected.as_ref().ok().map(|(_, value)| *value)),
+                _ => false,
+            };
+
+            let edge_idx = if tries_action {
+                let node = self.table.get_mut(&state).expect("the node was just added");
+                let (action, prior) = node.untried_actions.as_mut().and_then(|untried| untried.pop()).expect("there is an untried action");
+                node.edges.push(Edge::new(action, prior));
+                node.edges.len() - 1
+            } else {
+                selected?.0
+            };
+
+            let edge = &self.table[&state].edges[edge_idx];
+            let unvisited_edge = edge.num_visits == 0.0;
+            let next_state = state.apply_action(edge.action.clone(), rng).map_err(SearchError::IllegalAction)?;
+
+            path.push((state, edge_idx));
+            state = next_state;
+
+            // stop at the first new node or edge
+            if is_new || unvisited_edge {
+                break;
+            }
+        }
+
+        let rewards = self.config.leaf_rewards(&state, &self.root_state.players(), rng, None)?;
+
+        for (path_state, edge_idx) in path {
+            let node = self.table.get_mut(&path_state).expect("nodes on the search path are never dropped");
+            node.num_visits += 1.0;
+
+            let edge = &mut node.edges[edge_idx];
+            edge.num_visits += 1.0;
+            for (player, reward) in &rewards {
+                *edge.value.entry(player.clone()).or_insert(0f64) += reward;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expand(&mut self, state: &G) -> Result<(), SearchError<G::Error>> {
+        let actions = state.actions();
+
+        if actions.is_empty() {
+            return Err(SearchError::NoActions);
+        }
+
+        let priors = self.config.priors(state, &actions);
+        let mut untried_actions: Vec<(A, f64)> = actions.into_iter().zip(priors).collect();
+
+        let node = self.table.get_mut(state).expect("the node is expanded after it is added");
+
+        if self.config.expansion == ExpansionPolicy::Eager {
+            node.edges = untried_actions.into_iter().map(|(action, prior)| Edge::new(action, prior)).collect();
+            node.untried_actions = Some(Vec::new());
+            return Ok(());
+        }
+
+        order_untried_actions(&mut untried_actions);
+        node.untried_actions = Some(untried_actions);
+
+        Ok(())
+    }
+
+    /// The edge with the best selection value, with that value.
+    fn select<R: Rng>(&self, state: &G, rng: &mut R) -> Result<(usize, f64), SearchError<G::Error>> {
+        let node = &self.table[state];
+
+        let selected = node.edges.iter().enumerate().fold((None, f64::MIN), |acc, (edge_idx, edge)| {
+            let value = self.selection_value(node, edge, rng);
+            if value > acc.1 {
+                (Some(edge_idx), value)
+            } else {
+                acc
+            }
+        });
+
+        selected.0.map(|edge_idx| (edge_idx, selected.1)).ok_or(SearchError::NoActions)
+    }
+
+    fn selection_value<R: Rng>(&self, node: &TranspositionNode<P, A>, edge: &Edge<P, A>, rng: &mut R) -> f64 {
+        let noise = rng.gen::<f64>() * self.config.noise;
+        let constant_of_exploration = self.config.exploration_constant;
+
+        match self.config.selection {
+            SelectionPolicy::Ucb1 => {
+                if edge.num_visits == 0.0 {
+                    return f64::MAX;
+                }
+
+                let exploitation_component = edge.value_for(&node.mover) / edge.num_visits;
+                let exploration_component = constant_of_exploration * ((node.num_visits + 1.0).ln() / edge.num_visits).sqrt();
+
+                exploitation_component + exploration_component + noise
+            }
+            SelectionPolicy::Puct => {
+                let exploitation_component = if edge.num_visits == 0.0 {
+                    0f64
+                } else {
+                    edge.value_for(&node.mover) / edge.num_visits
+                };
+                let exploration_component = constant_of_exploration * edge.prior * node.num_visits.sqrt() / (1.0 + edge.num_visits);
+
+                exploitation_component + exploration_component + noise
+            }
+        }
+    }
+
+    /// Drops a batch of the lowest ranked nodes if the table is full.
+    fn make_room(&mut self, path: &[(G, usize)]) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+
+        if self.table.len() < capacity {
+            return;
+        }
+
+        let mut candidates: Vec<(&G, f64)> = self.table.iter()
+            .filter(|(state, _)| **state != self.root_state && !path.iter().any(|(path_state, _)| path_state == *state))
+            .map(|(state, node)| {
+                let rank = match self.replacement {
+                    ReplacementPolicy::LeastRecentlyUsed => node.last_used as f64,
+                    ReplacementPolicy::LeastVisited => node.num_visits,
+                };
+                (state, rank)
+            })
+            .collect();
+
+        // dropping a batch at a time saves ranking the whole table on every new node
+        let num_evicted = (capacity / 16).max(1).min(candidates.len());
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let evicted: Vec<G> = candidates.into_iter().take(num_evicted).map(|(state, _)| state.clone()).collect();
+        for state in evicted {
+            self.table.remove(&state);
+        }
+    }
+}
+
+struct TranspositionNode<P, A> {
+    /// the player to move in this state
+    mover: P,
+    num_visits: f64,
+    /// the search which last passed through this node
+    last_used: u64,
+    /// actions which don't have an edge yet with their priors, `None` until expanded
+    untried_actions: Option<Vec<(A, f64)>>,
+    edges: Vec<Edge<P, A>>,
+}
+
+impl<P, A> TranspositionNode<P, A> {
+    fn new(mover: P) -> Self {
+        TranspositionNode {
+            mover,
+            num_visits: 0.0,
+            last_used: 0,
+            untried_actions: None,
+            edges: Vec::new(),
+        }
+    }
+}
+
+struct Edge<P, A> {
+    action: A,
+    prior: f64,
+    num_visits: f64,
+    value: HashMap<P, f64>,
+}
+
+impl<P: Eq + Hash, A> Edge<P, A> {
+    fn new(action: A, prior: f64) -> Self {
+        Edge {
+            action,
+            prior,
+            num_visits: 0.0,
+            value: HashMap::new(),
+        }
+    }
+
+    fn value_for(&self, player: &P) -> f64 {
+        self.value.get(player).copied().unwrap_or(0f64)
+    }
+}
+
+/// Like `mcts_with_budget`, but with a `TranspositionTree`.
+pub fn mcts_transposition<
+    R: Rng + RngCore + Sized,
+    P: Eq + PartialEq + Hash + Clone,
+    A: Clone + PartialEq,
+    G: Mcts<P, A> + Hash + Eq
+>(game: &G, rng: &mut R, budget: &SearchBudget, config: MctsConfig<P, A, G>) -> Result<A, SearchError<G::Error>> {
+    let mut tree = TranspositionTree::from_state(game.clone(), config);
+
+    tree.search_budget(rng, budget)?;
+
+    best_or_first_action(game, tree.best_action())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use crate::mcts::test_games::Pile;
+    use super::*;
+
+    #[test]
+    fn make_room_keeps_the_root_and_the_search_path() {
+        for replacement in [ReplacementPolicy::LeastRecentlyUsed, ReplacementPolicy::LeastVisited] {
+            let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+            let root = Pile::new(16, 0);
+            let mut tree = TranspositionTree::from_state(root.clone(), MctsConfig::default()).with_capacity(8, replacement);
+
+            tree.search_n(&mut rng, 300).unwrap();
+            assert!(tree.num_nodes() <= 8);
+            assert!(tree.table.contains_key(&root));
+
+            // a path through rarely used states, which would otherwise be evicted first
+            let path: Vec<(Pile, usize)> = (1..=6).map(|stones| (Pile::new(stones, (stones % 2) as usize), 0)).collect();
+            for (state, _) in &path {
+                tree.table.entry(state.clone()).or_insert_with(|| TranspositionNode::new(state.current_player()));
+            }
+
+            while tree.table.len() > path.len() + 1 {
+                tree.make_room(&path);
+            }
+
+            assert!(tree.table.contains_key(&root));
+            assert!(path.iter().all(|(state, _)| tree.table.contains_key(state)));
+        }
+    }
+}
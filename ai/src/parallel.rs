@@ -0,0 +1,45 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of worker threads to use for `max_cores`, where 0 means one per available core.
+pub(crate) fn num_workers(max_cores: usize) -> usize {
+    if max_cores == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        max_cores
+    }
+}
+
+/// Runs `job` on every item using at most `max_cores` threads, returning the results in the same
+/// order as the items.
+pub(crate) fn run_bounded<I: Send, T: Send, F: Fn(I) -> T + Sync>(items: Vec<I>, max_cores: usize, job: F) -> Vec<T> {
+    let num_items = items.len();
+    let num_workers = num_workers(max_cores).min(num_items);
+
+    // not worth spinning up a thread for
+    if num_workers <= 1 {
+        return items.into_iter().map(job).collect();
+    }
+
+    let queue: Mutex<VecDeque<(usize, I)>> = Mutex::new(items.into_iter().enumerate().collect());
+    let results: Mutex<Vec<Option<T>>> = Mutex::new((0..num_items).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..num_workers {
+            scope.spawn(|| {
+                loop {
+                    let next = queue.lock().unwrap().pop_front();
+                    let Some((idx, item)) = next else {
+                        break;
+                    };
+
+                    let result = job(item);
+                    results.lock().unwrap()[idx] = Some(result);
+                }
+            });
+        }
+    });
+
+    results.into_inner().unwrap().into_iter().map(|result| result.expect("every item was processed")).collect()
+}
@@ -0,0 +1,51 @@
+use rand::{RngCore, SeedableRng};
+
+/// Splits one source of randomness into independent, reproducible random streams, e.g. one per
+/// thread or determinization.
+///
+/// Every stream is seeded from a SplitMix64 sequence keyed by the split's key and the stream's
+/// index, rather than being a clone of the parent advanced by a few steps, so streams don't overlap
+/// with each other or with the parent. The whole seed is derived, which for the `rand_pcg`
+/// generators includes the increment, so each of their streams also gets its own PCG stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RngStreams {
+    key: u64,
+}
+
+impl RngStreams {
+    pub fn from_seed(seed: u64) -> Self {
+        RngStreams {
+            key: seed,
+        }
+    }
+
+    /// Draws the key from `rng`, which moves it along so that the next split gives different streams.
+    pub fn from_rng<R: RngCore>(rng: &mut R) -> Self {
+        RngStreams {
+            key: rng.next_u64(),
+        }
+    }
+
+    /// The random stream with the given index. The same key and index always give the same stream.
+    pub fn stream<R: SeedableRng>(&self, stream_idx: u64) -> R {
+        let mut index_state = stream_idx;
+        let mut state = self.key ^ splitmix64(&mut index_state);
+
+        let mut seed = R::Seed::default();
+        for chunk in seed.as_mut().chunks_mut(8) {
+            let bytes = splitmix64(&mut state).to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+
+        R::from_seed(seed)
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,87 @@
+use rand::Rng;
+use ai::{Determinable, Mcts, Observable, Outcome};
+
+/// Player 0 antes, player 1 hides one of two cards without showing it, and player 0 wins if they
+/// guess which one it was.
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Default)]
+pub struct HiddenCard {
+    pub anted: bool,
+    pub card: Option<u8>,
+    pub guess: Option<u8>,
+}
+
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub enum Move {
+    Ante,
+    Hide(u8),
+    /// how player 0 sees player 1 hiding a card
+    Hidden,
+    Guess(u8),
+}
+
+impl Mcts<usize, Move> for HiddenCard {
+    type Error = ();
+
+    fn actions(&self) -> Vec<Move> {
+        if !self.anted {
+            vec![Move::Ante]
+        } else if self.card.is_none() {
+            vec![Move::Hide(0), Move::Hide(1)]
+        } else if self.guess.is_none() {
+            vec![Move::Guess(0), Move::Guess(1)]
+        } else {
+            vec![]
+        }
+    }
+
+    fn apply_action<R: Rng + Sized>(&self, action: Move, _rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+        if !self.actions().contains(&action) {
+            return Err(());
+        }
+
+        let mut state = self.clone();
+        match action {
+            Move::Ante => state.anted = true,
+            Move::Hide(card) => state.card = Some(card),
+            Move::Guess(card) => state.guess = Some(card),
+            Move::Hidden => return Err(()),
+        }
+
+        Ok(state)
+    }
+
+    fn outcome(&self) -> Option<Outcome<usize>> {
+        let guess = self.guess?;
+
+        Some(Outcome::Winner(if Some(guess) == self.card { 0 } else { 1 }))
+    }
+
+    fn current_player(&self) -> usize {
+        if self.anted && self.card.is_none() { 1 } else { 0 }
+    }
+
+    fn players(&self) -> Vec<usize> {
+        vec![0, 1]
+    }
+}
+
+impl Determinable<usize, Move, HiddenCard> for HiddenCard {
+    fn determine<R: Rng>(&self, rng: &mut R, perspective_player: usize) -> HiddenCard {
+        let mut state = self.clone();
+
+        if perspective_player == 0 && state.card.is_some() {
+            state.card = Some(rng.gen_range(0..2));
+        }
+
+        state
+    }
+}
+
+impl Observable<usize, Move> for HiddenCard {
+    fn observe(&self, action: &Move, observer: &usize) -> Move {
+        match action {
+            Move::Hide(_) if *observer == 0 => Move::Hidden,
+            _ => *action,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use rand::Rng;
+use ai::{Determinable, Mcts, Observable, Outcome};
+
+/// Players take 1 to 3 stones in turn, whoever takes the last stone wins. The player to move wins
+/// by leaving a multiple of 4.
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct Nim {
+    pub stones: u8,
+    pub player: usize,
+}
+
+impl Mcts<usize, u8> for Nim {
+    type Error = ();
+
+    fn actions(&self) -> Vec<u8> {
+        (1..=self.stones.min(3)).collect()
+    }
+
+    fn apply_action<R: Rng + Sized>(&self, action: u8, _rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+        if action == 0 || action > self.stones {
+            return Err(());
+        }
+
+        let stones = self.stones - action;
+
+        // the winner stays the current player once the last stone is taken
+        let player = if stones == 0 { self.player } else { 1 - self.player };
+
+        Ok(Nim { stones, player })
+    }
+
+    fn outcome(&self) -> Option<Outcome<usize>> {
+        (self.stones == 0).then_some(Outcome::Winner(self.player))
+    }
+
+    fn current_player(&self) -> usize {
+        self.player
+    }
+
+    fn players(&self) -> Vec<usize> {
+        vec![0, 1]
+    }
+}
+
+/// Nothing is hidden in nim, every determinization is the state itself.
+impl Determinable<usize, u8, Nim> for Nim {
+    fn determine<R: Rng>(&self, _rng: &mut R, _perspective_player: usize) -> Nim {
+        self.clone()
+    }
+}
+
+impl Observable<usize, u8> for Nim {
+    fn observe(&self, action: &u8, _observer: &usize) -> u8 {
+        *action
+    }
+}
@@ -1,7 +1,7 @@
 use std::ops::Range;
 use std::path::Iter;
 use rand::{Rng};
-use mcts::{Mcts, Outcome};
+use ai::{Mcts, Outcome};
 
 #[derive(Eq, PartialEq, Hash, Clone)]
 pub struct NumberGamePlayer {
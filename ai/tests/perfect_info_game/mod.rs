@@ -1,6 +1,6 @@
 use std::ops::Range;
 use rand::{Rng};
-use mcts::{Mcts, Outcome};
+use ai::{Mcts, Outcome};
 
 #[derive(Eq, PartialEq, Hash, Clone)]
 pub struct PogPlayer {
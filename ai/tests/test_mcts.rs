@@ -1,5 +1,11 @@
 use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use ai::{ismcts_mt, ismcts_mt_stats, mcts, mcts_open_loop, mcts_root_parallel, mcts_transposition, mcts_tree_parallel, mcts_with_budget, mo_ismcts, so_ismcts, ChildStats, Cutoff, DecisiveMoves, DepthLimited, Determinable, ExpansionPolicy, Mast, Mcts, MctsConfig, MultiObserverTrees, OpenLoopTree, Outcome, Prior, RaveSchedule, RngStreams, RolloutGuard, RolloutPolicy, RootParallelTrees, SearchBudget, SearchError, SelectionPolicy, TranspositionTree, UniformRollout, VecTree};
 
+mod hidden_card;
+mod nim;
 mod number_game;
 mod perfect_info_game;
 
@@ -10,3 +16,399 @@ fn tree_test() {
 
     let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
 }
+
+#[test]
+fn rng_streams_give_distinct_rollouts() {
+    let game = number_game::NumberGame::default();
+    let streams = RngStreams::from_seed(0);
+
+    // every player selects a few numbers at random
+    let rollout = |mut rng: rand_pcg::Pcg64| {
+        let mut game = game.clone();
+        for _ in 0..4 {
+            let actions = game.actions();
+            let action = actions[rng.gen_range(0..actions.len())].clone();
+            game = game.apply_action(action, &mut rng).unwrap();
+        }
+        game
+    };
+
+    let rollouts: Vec<_> = (0..8).map(|stream_idx| rollout(streams.stream(stream_idx))).collect();
+
+    for (idx, a) in rollouts.iter().enumerate() {
+        for b in rollouts.iter().skip(idx + 1) {
+            assert!(a != b, "independent streams should play out differently");
+        }
+    }
+
+    // the same stream is reproducible
+    assert!(rollout(streams.stream(3)) == rollouts[3]);
+}
+
+#[test]
+fn vec_tree_introspection() {
+    let game = nim::Nim { stones: 10, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    let mut tree = VecTree::from_state(game, MctsConfig::default());
+    tree.search_n(&mut rng, 2000).unwrap();
+
+    let stats = tree.root_stats();
+    assert!(!stats.is_empty());
+
+    // every iteration passes through exactly one of the root's children
+    let child_visits: f64 = stats.iter().map(|child| child.num_visits).sum();
+    assert_eq!(child_visits, tree.root_visits());
+    assert_eq!(tree.root_visits(), 2000.0);
+
+    assert!(tree.num_nodes() > stats.len());
+    assert!(tree.depth() >= 1);
+
+    // the principal variation starts with the most visited child, ties going to the earliest
+    let most_visited = stats.iter().fold(None, |acc: Option<&ChildStats<_, _>>, child| match acc {
+        Some(acc) if acc.num_visits >= child.num_visits => Some(acc),
+        _ => Some(child),
+    }).unwrap();
+    let variation = tree.principal_variation();
+    assert!(!variation.is_empty() && variation.len() <= tree.depth());
+    assert!(variation[0] == most_visited.action);
+
+    // taking 2 leaves a multiple of 4, which wins
+    assert_eq!(variation[0], 2);
+
+    for child in stats.iter().filter(|child| child.num_visits > 0.0) {
+        let (low, high) = child.confidence_interval(1.96);
+        assert!(low <= child.average_reward && child.average_reward <= high);
+        assert!((0.0..=1.0).contains(&child.average_reward));
+    }
+}
+
+#[test]
+fn rollout_guard_composes_with_the_wrapped_policy() {
+    let game = nim::Nim { stones: 10, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    // the wrapped policy still learns from the guarded playouts
+    let guard = RolloutGuard::new(Mast::new(1.0), 100, Cutoff::Draw);
+    for _ in 0..50 {
+        guard.rollout(&game, &mut rng, None).unwrap();
+    }
+    assert!(guard.policy.average(&1).is_some());
+    assert_eq!(guard.stats().num_rollouts, 50);
+    assert_eq!(guard.stats().num_depth_cutoffs, 0);
+
+    // and the wrapped policy's own cut off still applies
+    let guard = RolloutGuard::new(DepthLimited::new(UniformRollout, 1, |_: &nim::Nim| Outcome::Winner(7)), 100, Cutoff::Draw);
+    assert!(matches!(guard.rollout(&game, &mut rng, None).unwrap(), Outcome::Winner(7)));
+
+    // the guard's stats can be read through the Arc it was configured with
+    let guard = Arc::new(RolloutGuard::new(UniformRollout, 2, Cutoff::Evaluate(Arc::new(|_: &nim::Nim| Outcome::Winner(0)))));
+    let config = MctsConfig { rollout: guard.clone(), ..MctsConfig::default() };
+
+    let mut tree = VecTree::from_state(game, config);
+    tree.search_n(&mut rng, 50).unwrap();
+
+    // leaves where the game is already over don't need a rollout
+    let stats = guard.stats();
+    assert!(stats.num_rollouts > 0 && stats.num_rollouts <= 50);
+    assert!(stats.num_depth_cutoffs > 0);
+}
+
+#[test]
+fn decisive_moves_composes_with_the_wrapped_policy() {
+    let game = nim::Nim { stones: 10, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    // the wrapped policy learns from the playouts, including the decisive moves picked for it
+    let decisive = DecisiveMoves::new(Mast::new(1.0), 16);
+    for _ in 0..50 {
+        decisive.rollout(&game, &mut rng, None).unwrap();
+    }
+    assert!(decisive.policy.average(&1).is_some());
+
+    // and the wrapped policy's depth limit still applies
+    let decisive = DecisiveMoves::new(DepthLimited::new(UniformRollout, 1, |_: &nim::Nim| Outcome::Winner(7)), 16);
+    assert!(matches!(decisive.rollout(&game, &mut rng, None).unwrap(), Outcome::Winner(7)));
+
+    // with 3 stones left, taking them all wins
+    let last_stones = nim::Nim { stones: 3, player: 0 };
+    assert_eq!(decisive.choose(&last_stones, last_stones.actions(), &mut rng), 3);
+}
+
+#[test]
+fn best_action_skips_unvisited_children() {
+    let game = nim::Nim { stones: 10, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    // eager expansion adds every child at once, but two iterations only visit two of them
+    let config = MctsConfig { expansion: ExpansionPolicy::Eager, ..MctsConfig::default() };
+    let mut tree = VecTree::from_state(game, config);
+    tree.search_n(&mut rng, 2).unwrap();
+
+    let best_action = tree.best_action().unwrap();
+    let best_child = tree.root_stats().into_iter().find(|child| child.action == best_action).unwrap();
+    assert!(best_child.num_visits > 0.0);
+}
+
+// a prior which all but rules out taking more than one stone
+impl Prior<u8> for nim::Nim {
+    fn priors(&self, actions: &[u8]) -> Vec<f64> {
+        actions.iter().map(|action| if *action == 1 { 0.98 } else { 0.01 }).collect()
+    }
+}
+
+#[test]
+fn puct_passes_over_actions_its_priors_rule_out() {
+    // taking one stone leaves a multiple of 4, which wins, so the prior is right
+    let game = nim::Nim { stones: 9, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    let mut tree = VecTree::from_state(game.clone(), MctsConfig::default().with_priors());
+    tree.search_n(&mut rng, 200).unwrap();
+    assert_eq!(tree.root_stats().len(), 1);
+    assert_eq!(tree.best_action(), Some(1));
+
+    let mut tree = OpenLoopTree::from_state(game.clone(), MctsConfig::default().with_priors());
+    tree.search_n(&mut rng, 200).unwrap();
+    assert_eq!(tree.best_action(), Some(1));
+
+    let mut tree = TranspositionTree::from_state(game, MctsConfig::default().with_priors());
+    tree.search_n(&mut rng, 200).unwrap();
+    assert_eq!(tree.best_action(), Some(1));
+}
+
+#[test]
+fn priors_and_evaluators_can_capture() {
+    let game = nim::Nim { stones: 9, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    let favourite = 1u8;
+    let evaluations = Arc::new(AtomicUsize::new(0));
+    let counter = evaluations.clone();
+
+    let config = MctsConfig {
+        selection: SelectionPolicy::Puct,
+        prior: Some(Arc::new(move |_: &nim::Nim, actions: &[u8]| actions.iter().map(|action| if *action == favourite { 0.98 } else { 0.01 }).collect())),
+        evaluator: Some(Arc::new(move |_: &nim::Nim| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            HashMap::new()
+        })),
+        evaluation_weight: 0.5,
+        ..MctsConfig::default()
+    };
+
+    let mut tree = VecTree::from_state(game, config);
+    tree.search_n(&mut rng, 200).unwrap();
+
+    assert_eq!(tree.best_action(), Some(favourite));
+    assert!(evaluations.load(Ordering::Relaxed) > 0);
+}
+
+/// A game which isn't over but has nothing left to play.
+#[derive(Clone, Debug)]
+struct Stuck;
+
+impl Mcts<usize, u8> for Stuck {
+    type Error = ();
+
+    fn actions(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn apply_action<R: Rng + Sized>(&self, _action: u8, _rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+        Err(())
+    }
+
+    fn outcome(&self) -> Option<Outcome<usize>> {
+        None
+    }
+
+    fn current_player(&self) -> usize {
+        0
+    }
+
+    fn players(&self) -> Vec<usize> {
+        vec![0, 1]
+    }
+}
+
+impl Determinable<usize, u8, Stuck> for Stuck {
+    fn determine<R: Rng>(&self, _rng: &mut R, _perspective_player: usize) -> Stuck {
+        Stuck
+    }
+}
+
+#[test]
+fn so_ismcts_without_actions_is_an_error() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+
+    let finished = nim::Nim { stones: 0, player: 0 };
+    assert!(matches!(so_ismcts(&finished, &mut rng, 10, Default::default()), Err(SearchError::NoActions)));
+
+    assert!(matches!(so_ismcts(&Stuck, &mut rng, 10, Default::default()), Err(SearchError::NoActions)));
+}
+
+#[test]
+fn so_ismcts_finds_the_winning_move() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+
+    // taking 2 leaves a multiple of 4
+    let game = nim::Nim { stones: 6, player: 0 };
+    assert_eq!(so_ismcts(&game, &mut rng, 2000, MctsConfig::default()).unwrap(), 2);
+
+    let config = MctsConfig { exploration_constant: 0.7, rollout: Arc::new(DecisiveMoves::new(UniformRollout, 3)), ..MctsConfig::default() };
+    assert_eq!(so_ismcts(&game, &mut rng, 2000, config).unwrap(), 2);
+}
+
+#[test]
+fn hidden_actions_share_a_node_in_the_observers_tree() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+    let mut trees = MultiObserverTrees::from_state(hidden_card::HiddenCard::default(), MctsConfig::default());
+
+    trees.search_n(&mut rng, 200).unwrap();
+
+    // player 0 can't tell which card was hidden: root, ante, hidden, and the two guesses
+    assert_eq!(trees.num_nodes_of(&0), Some(5));
+    // player 1 knows: root, ante, both cards, and two guesses after each
+    assert_eq!(trees.num_nodes_of(&1), Some(8));
+    assert_eq!(trees.best_action(), Some(hidden_card::Move::Ante));
+
+    assert!(matches!(mo_ismcts(&nim::Nim { stones: 0, player: 0 }, &mut rng, 10, Default::default()), Err(SearchError::NoActions)));
+}
+
+#[test]
+fn ismcts_mt_without_actions_is_an_error() {
+    let rng = rand_pcg::Pcg32::seed_from_u64(0);
+    let finished = nim::Nim { stones: 0, player: 0 };
+
+    assert!(matches!(ismcts_mt(&finished, &rng, 4, 10, Default::default(), 2), Err(SearchError::NoActions)));
+    assert!(matches!(ismcts_mt_stats(&finished, &rng, 4, 10, Default::default(), 2), Err(SearchError::NoActions)));
+}
+
+#[test]
+fn root_parallel_merges_the_trees_root_children() {
+    let game = nim::Nim { stones: 10, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    let mut trees = RootParallelTrees::from_state(game, MctsConfig::default(), 3, 2);
+    trees.search_n(&mut rng, 100).unwrap();
+
+    let merged = trees.root_children();
+    assert_eq!(merged.len(), 3);
+
+    for (action, visits, value) in merged {
+        let children: Vec<ChildStats<usize, u8>> = trees.trees().iter()
+            .flat_map(|tree| tree.root_stats())
+            .filter(|child| child.action == action)
+            .collect();
+
+        assert_eq!(children.len(), 3);
+        assert_eq!(visits, children.iter().map(|child| child.num_visits).sum::<f64>());
+        assert!((value - children.iter().map(|child| child.values[&0]).sum::<f64>()).abs() < 1e-9);
+    }
+
+    let total_visits: f64 = trees.root_children().iter().map(|(_, visits, _)| visits).sum();
+    assert_eq!(total_visits, 300.0);
+}
+
+/// Player 0 tosses a coin which decides who moves next, and whoever it is wins by choosing their own
+/// number.
+#[derive(Clone, Debug)]
+struct CoinToss {
+    mover: Option<usize>,
+    winner: Option<usize>,
+}
+
+impl Mcts<usize, u8> for CoinToss {
+    type Error = ();
+
+    fn actions(&self) -> Vec<u8> {
+        match (self.mover, self.winner) {
+            (_, Some(_)) => vec![],
+            (None, None) => vec![0],
+            (Some(_), None) => vec![0, 1],
+        }
+    }
+
+    fn apply_action<R: Rng + Sized>(&self, action: u8, rng: &mut R) -> Result<Self, Self::Error> where Self: Sized {
+        match self.mover {
+            None => Ok(CoinToss { mover: Some(rng.gen_range(0..2)), winner: None }),
+            Some(mover) => Ok(CoinToss { mover: Some(mover), winner: Some(if action as usize == mover { mover } else { 1 - mover }) }),
+        }
+    }
+
+    fn outcome(&self) -> Option<Outcome<usize>> {
+        self.winner.map(Outcome::Winner)
+    }
+
+    fn current_player(&self) -> usize {
+        self.mover.unwrap_or(0)
+    }
+
+    fn players(&self) -> Vec<usize> {
+        vec![0, 1]
+    }
+}
+
+#[test]
+fn open_loop_gives_each_mover_their_own_children() {
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+    let mut tree = OpenLoopTree::from_state(CoinToss { mover: None, winner: None }, MctsConfig::default());
+
+    tree.search_n(&mut rng, 200).unwrap();
+
+    // the root, the toss, and both choices for each player the coin may pick
+    assert_eq!(tree.num_nodes(), 6);
+
+    // once the coin picked player 1, only their choices are considered
+    tree.advance(&0, CoinToss { mover: Some(1), winner: None });
+    assert_eq!(tree.num_nodes(), 5);
+    assert_eq!(tree.best_action(), Some(1));
+}
+
+#[test]
+fn spent_budgets_fall_back_to_the_first_action() {
+    let game = nim::Nim { stones: 5, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+    let budget = SearchBudget::default().with_cancel(Arc::new(AtomicBool::new(true)));
+
+    assert_eq!(mcts_with_budget(&game, &mut rng, &budget, MctsConfig::default()).unwrap(), 1);
+    assert_eq!(mcts_open_loop(&game, &mut rng, &budget, MctsConfig::default()).unwrap(), 1);
+    assert_eq!(mcts_transposition(&game, &mut rng, &budget, MctsConfig::default()).unwrap(), 1);
+    assert_eq!(mcts_root_parallel(&game, &mut rng, &budget, MctsConfig::default(), 2, 2).unwrap(), 1);
+    assert_eq!(mcts_tree_parallel(&game, &mut rng, &budget, MctsConfig::default(), 2).unwrap(), 1);
+
+    let finished = nim::Nim { stones: 0, player: 0 };
+    assert!(matches!(mcts_with_budget(&finished, &mut rng, &budget, MctsConfig::default()), Err(SearchError::NoActions)));
+}
+
+#[test]
+fn searches_without_iterations_fall_back_to_the_first_action() {
+    let game = nim::Nim { stones: 5, player: 0 };
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+
+    assert_eq!(mcts(&game, &mut rng, 0, MctsConfig::default()).unwrap(), 1);
+    assert_eq!(so_ismcts(&game, &mut rng, 0, Default::default()).unwrap(), 1);
+    assert_eq!(mo_ismcts(&game, &mut rng, 0, Default::default()).unwrap(), 1);
+
+    let finished = nim::Nim { stones: 0, player: 0 };
+    assert!(matches!(mcts(&finished, &mut rng, 0, MctsConfig::default()), Err(SearchError::NoActions)));
+}
+
+#[test]
+fn rave_counts_every_visit_as_an_amaf_visit() {
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(0);
+    let config = MctsConfig { rave: Some(RaveSchedule::MinimumMse(0.1)), ..MctsConfig::default() };
+    let mut tree = VecTree::from_state(nim::Nim { stones: 7, player: 0 }, config);
+
+    tree.search_n(&mut rng, 2000).unwrap();
+
+    // a child's move is played in every simulation through it, and in some that got to it later
+    for child in tree.root_stats() {
+        assert!(child.num_visits > 0.0);
+        assert!(child.amaf_visits >= child.num_visits);
+    }
+
+    // taking 3 leaves a multiple of 4
+    assert_eq!(tree.best_action(), Some(3));
+}
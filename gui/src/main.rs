@@ -115,12 +115,12 @@ impl<
             num_sims: self.num_sims,
             max_cores: 0,
             sim_players: vec![
-                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10 },
-                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10 },
-                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10 },
-                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10 },
+                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10, budget: None },
+                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10, budget: None },
+                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10, budget: None },
+                ISMCTSPlayerParams { num_determinations: 4, num_simulations_per_action: 10, budget: None },
             ],
-        });
+        }).expect("the simulated games should not break the rules");
 
         let mut graph = Graph::from(&game_graph);
 